@@ -0,0 +1,133 @@
+/*
+ * C interface of umi-transfer.
+ *
+ * Build the library with `cargo build --release -p umi-transfer-capi`, which creates
+ * libumi_transfer_c.so (or .dylib) and libumi_transfer_c.a in target/release.
+ *
+ * Records
+ *   Records are passed as a buffer with one FastQ record, i.e. the four lines of header, sequence,
+ *   separator and qualities. The trailing newline is optional. Edited records are returned in the
+ *   same format, always ending with a newline.
+ *
+ * Ownership
+ *   All pointers passed to the library remain owned by the caller and are only used during the
+ *   call. Buffers returned in an UmiTransferBuffer are owned by the caller and must be released
+ *   with umi_transfer_buffer_free(). Strings are NUL-terminated UTF-8. Optional arguments are
+ *   passed as NULL.
+ *
+ * Errors
+ *   All functions that can fail return 0 on success. Otherwise they return the exit code that
+ *   `umi-transfer` would exit with for the same error (see the README), or 2 for invalid arguments
+ *   such as NULL pointers. Output arguments are only written on success. A description of the
+ *   last error on the calling thread is returned by umi_transfer_last_error().
+ *
+ * Threads
+ *   The functions can be called from several threads at the same time.
+ */
+
+#ifndef UMI_TRANSFER_H
+#define UMI_TRANSFER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define UMI_TRANSFER_OK 0
+
+/* Target position of the UMI in umi_transfer_files(). */
+#define UMI_TRANSFER_HEADER 0
+#define UMI_TRANSFER_INLINE 1
+
+/* What to do with reads whose name already ends with a UMI, as with --on-existing-umi. */
+#define UMI_TRANSFER_EXISTING_ERROR 0
+#define UMI_TRANSFER_EXISTING_REPLACE 1
+#define UMI_TRANSFER_EXISTING_APPEND 2
+#define UMI_TRANSFER_EXISTING_SKIP 3
+
+/* End of the read to which an inline UMI is added, as with --inline-end. */
+#define UMI_TRANSFER_5P 0
+#define UMI_TRANSFER_3P 1
+
+/* Buffer allocated by the library. Release it with umi_transfer_buffer_free(). */
+typedef struct UmiTransferBuffer {
+    uint8_t *data;
+    size_t len;
+} UmiTransferBuffer;
+
+/*
+ * Settings of umi_transfer_files(). Start from umi_transfer_options_default(), which sets
+ * struct_size. Options with another struct_size, e.g. from a different version of this header,
+ * are rejected with status 2.
+ */
+typedef struct UmiTransferOptions {
+    size_t struct_size;          /* sizeof(UmiTransferOptions) */
+    int32_t position;            /* UMI_TRANSFER_HEADER or UMI_TRANSFER_INLINE */
+    bool correct_numbers;        /* as --correct_numbers */
+    const char *delim;           /* NULL for ":" */
+    const char *header_template; /* NULL for "{id}{delim}{umi} {desc}" */
+    int32_t on_existing_umi;     /* UMI_TRANSFER_EXISTING_* */
+    bool gzip;                   /* compress the outputs */
+    uint32_t compression_level;  /* 0 for the default level 3 */
+    size_t threads;              /* 0 for all available cores */
+    bool force;                  /* overwrite existing outputs, otherwise they fail with status 4 */
+} UmiTransferOptions;
+
+/*
+ * Adds a UMI to the header of a record, like `--position header`.
+ *
+ * umi and umi_qual point to umi_len bytes each. If umi_qual is NULL, the qualities are 'I'.
+ * read_nr is the read number (1 or 2) for the {read_nr} placeholder and, with correct_numbers,
+ * for the description. The edited record is written to out.
+ */
+int32_t umi_transfer_record_header(const uint8_t *record, size_t record_len, const uint8_t *umi,
+                                   const uint8_t *umi_qual, size_t umi_len, const char *delim,
+                                   const char *header_template, uint8_t read_nr,
+                                   bool correct_numbers, int32_t on_existing_umi,
+                                   UmiTransferBuffer *out);
+
+/*
+ * Adds a UMI to the sequence of a record, like `--position inline`.
+ *
+ * umi and umi_qual are given as for umi_transfer_record_header(). spacer is an optional sequence
+ * between UMI and read, whose bases get the quality spacer_qual, or 'I' if it is 0. If read_nr is
+ * not 0, the read number in the description is corrected to it. The edited record is written to
+ * out.
+ */
+int32_t umi_transfer_record_inline(const uint8_t *record, size_t record_len, const uint8_t *umi,
+                                   const uint8_t *umi_qual, size_t umi_len, int32_t inline_end,
+                                   const char *spacer, char spacer_qual, uint8_t read_nr,
+                                   UmiTransferBuffer *out);
+
+/* Returns the settings of `umi-transfer external` without further options. */
+UmiTransferOptions umi_transfer_options_default(void);
+
+/*
+ * Transfers the UMIs of a FastQ file to a pair of read files, like `umi-transfer external`.
+ *
+ * out1 and out2 are optional and default to the inputs with a `_with_UMIs` suffix. options may be
+ * NULL for the defaults. If records is not NULL, the number of processed record pairs is written
+ * to it. Nothing is printed and the caller is never asked before an existing output is replaced:
+ * without options->force, existing outputs are kept and the call fails with status 4.
+ */
+int32_t umi_transfer_files(const char *r1, const char *r2, const char *umi, const char *out1,
+                           const char *out2, const UmiTransferOptions *options,
+                           uint64_t *records);
+
+/* Releases a buffer returned by the library. Buffers with a NULL data pointer are ignored. */
+void umi_transfer_buffer_free(UmiTransferBuffer buffer);
+
+/*
+ * Returns a description of the last error on the calling thread, or NULL if the last call
+ * succeeded. The string is owned by the library and valid until the next call on this thread.
+ */
+const char *umi_transfer_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* UMI_TRANSFER_H */
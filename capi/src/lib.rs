@@ -0,0 +1,596 @@
+// C interface of the transfer logic. The functions, their arguments and the ownership of buffers are
+// documented in include/umi_transfer.h, which has to be kept in sync with this file.
+#![allow(clippy::missing_safety_doc)]
+
+use anyhow::{anyhow, Result};
+use bio::io::fastq::{Reader, Record, Writer};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use umi_transfer::header_template::{HeaderTemplate, DEFAULT_TEMPLATE};
+use umi_transfer::read_editing::{
+    correct_read_nr, umi_to_record_header, umi_to_record_seq, ExistingUMI, InlineEnd, InlineLayout,
+    InlineReads, UMIDestination,
+};
+use umi_transfer::umi_errors::{exit_code, EXIT_FAILURE, EXIT_USAGE};
+use umi_transfer::umi_source::DEFAULT_UMI_QUAL;
+use umi_transfer::{OverwritePolicy, RuntimeErrors, UmiTransfer};
+
+pub const UMI_TRANSFER_OK: i32 = 0;
+
+pub const UMI_TRANSFER_HEADER: i32 = 0;
+pub const UMI_TRANSFER_INLINE: i32 = 1;
+
+pub const UMI_TRANSFER_EXISTING_ERROR: i32 = 0;
+pub const UMI_TRANSFER_EXISTING_REPLACE: i32 = 1;
+pub const UMI_TRANSFER_EXISTING_APPEND: i32 = 2;
+pub const UMI_TRANSFER_EXISTING_SKIP: i32 = 3;
+
+pub const UMI_TRANSFER_5P: i32 = 0;
+pub const UMI_TRANSFER_3P: i32 = 1;
+
+// Buffer allocated by the library, which must be released with umi_transfer_buffer_free().
+#[repr(C)]
+pub struct UmiTransferBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+// Settings of umi_transfer_files(). umi_transfer_options_default() returns the defaults of
+// `umi-transfer external`. struct_size holds the size of the struct the caller was compiled with,
+// so that a caller built against another version of the header is detected.
+#[repr(C)]
+pub struct UmiTransferOptions {
+    pub struct_size: usize,
+    pub position: i32,
+    pub correct_numbers: bool,
+    pub delim: *const c_char,
+    pub header_template: *const c_char,
+    pub on_existing_umi: i32,
+    pub gzip: bool,
+    pub compression_level: u32,
+    pub threads: usize,
+    pub force: bool,
+}
+
+// Errors that are caused by the caller rather than the data, e.g. a null pointer.
+#[derive(Debug)]
+struct InvalidArgument(String);
+
+impl std::fmt::Display for InvalidArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid argument: {}.", self.0)
+    }
+}
+
+impl std::error::Error for InvalidArgument {}
+
+fn invalid(reason: impl Into<String>) -> anyhow::Error {
+    anyhow!(InvalidArgument(reason.into()))
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Runs a function of the interface, stores its error for umi_transfer_last_error() and returns
+// the status. Panics must not unwind into the caller, so they are reported as failure.
+fn status(function: impl FnOnce() -> Result<()>) -> i32 {
+    let (code, message) = match catch_unwind(AssertUnwindSafe(function)) {
+        Ok(Ok(())) => (UMI_TRANSFER_OK, None),
+        Ok(Err(error)) if error.is::<InvalidArgument>() => {
+            (EXIT_USAGE, Some(format!("{:#}", error)))
+        }
+        Ok(Err(error)) => (exit_code(&error), Some(format!("{:#}", error))),
+        Err(_) => (
+            EXIT_FAILURE,
+            Some("Internal error of umi-transfer.".to_string()),
+        ),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = message.map(|message| {
+            CString::new(message.replace('\0', " ")).expect("NUL bytes were replaced")
+        })
+    });
+    code
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize, name: &str) -> Result<&'a [u8]> {
+    if data.is_null() {
+        return if len == 0 {
+            Ok(&[])
+        } else {
+            Err(invalid(format!("{} is null", name)))
+        };
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+// Optional strings are null pointers if not given.
+unsafe fn optional_str<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map(Some)
+        .map_err(|_| invalid(format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn required_str<'a>(value: *const c_char, name: &str) -> Result<&'a str> {
+    optional_str(value, name)?.ok_or_else(|| invalid(format!("{} is null", name)))
+}
+
+fn parse_record(data: &[u8]) -> Result<Record> {
+    Reader::new(data)
+        .records()
+        .next()
+        .ok_or_else(|| invalid("record does not contain a FastQ record"))?
+        .map_err(|source| {
+            anyhow!(RuntimeErrors::InvalidFastQ {
+                path: "record buffer".into(),
+                record: 1,
+                source
+            })
+        })
+}
+
+// Writes the record to a buffer owned by the caller.
+unsafe fn write_record(record: &Record, out: *mut UmiTransferBuffer) -> Result<()> {
+    if out.is_null() {
+        return Err(invalid("out is null"));
+    }
+    let mut data = Vec::new();
+    // The writer is flushed explicitly, as errors would go unnoticed when it is dropped.
+    let mut writer = Writer::new(&mut data);
+    writer
+        .write_record(record)
+        .and_then(|_| writer.flush())
+        .map_err(|source| {
+            anyhow!(RuntimeErrors::ReadWriteError {
+                id: record.id().to_string(),
+                source
+            })
+        })?;
+    drop(writer);
+    let data = Box::into_raw(data.into_boxed_slice());
+    *out = UmiTransferBuffer {
+        data: data as *mut u8,
+        len: data.len(),
+    };
+    Ok(())
+}
+
+// Qualities of the UMI, which default to 'I' if not given.
+unsafe fn umi_with_qual(
+    umi: *const u8,
+    umi_qual: *const u8,
+    umi_len: usize,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let seq = bytes(umi, umi_len, "umi")?.to_vec();
+    let qual = if umi_qual.is_null() {
+        vec![DEFAULT_UMI_QUAL; umi_len]
+    } else {
+        bytes(umi_qual, umi_len, "umi_qual")?.to_vec()
+    };
+    Ok((seq, qual))
+}
+
+fn existing_umi(value: i32) -> Result<ExistingUMI> {
+    match value {
+        UMI_TRANSFER_EXISTING_ERROR => Ok(ExistingUMI::Error),
+        UMI_TRANSFER_EXISTING_REPLACE => Ok(ExistingUMI::Replace),
+        UMI_TRANSFER_EXISTING_APPEND => Ok(ExistingUMI::Append),
+        UMI_TRANSFER_EXISTING_SKIP => Ok(ExistingUMI::Skip),
+        _ => Err(invalid(format!("{} is not a valid on_existing_umi", value))),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn umi_transfer_record_header(
+    record: *const u8,
+    record_len: usize,
+    umi: *const u8,
+    umi_qual: *const u8,
+    umi_len: usize,
+    delim: *const c_char,
+    header_template: *const c_char,
+    read_nr: u8,
+    correct_numbers: bool,
+    on_existing_umi: i32,
+    out: *mut UmiTransferBuffer,
+) -> i32 {
+    status(|| {
+        let input = parse_record(bytes(record, record_len, "record")?)?;
+        let (umi, umi_qual) = umi_with_qual(umi, umi_qual, umi_len)?;
+        let template = HeaderTemplate::new(
+            optional_str(header_template, "header_template")?.unwrap_or(DEFAULT_TEMPLATE),
+            optional_str(delim, "delim")?.unwrap_or(":"),
+            None,
+        )?;
+        let edited = umi_to_record_header(
+            input,
+            &umi,
+            &umi_qual,
+            &template,
+            read_nr,
+            correct_numbers,
+            &existing_umi(on_existing_umi)?,
+        )?;
+        write_record(&edited, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn umi_transfer_record_inline(
+    record: *const u8,
+    record_len: usize,
+    umi: *const u8,
+    umi_qual: *const u8,
+    umi_len: usize,
+    inline_end: i32,
+    spacer: *const c_char,
+    spacer_qual: c_char,
+    read_nr: u8,
+    out: *mut UmiTransferBuffer,
+) -> i32 {
+    status(|| {
+        let input = parse_record(bytes(record, record_len, "record")?)?;
+        let (umi, umi_qual) = umi_with_qual(umi, umi_qual, umi_len)?;
+        let end = match inline_end {
+            UMI_TRANSFER_5P => InlineEnd::FivePrime,
+            UMI_TRANSFER_3P => InlineEnd::ThreePrime,
+            _ => return Err(invalid(format!("{} is not a valid inline_end", inline_end))),
+        };
+        let layout = InlineLayout::new(
+            end,
+            InlineReads::Both,
+            optional_str(spacer, "spacer")?,
+            (spacer_qual != 0).then_some(spacer_qual as u8 as char),
+        )?;
+        let edited = umi_to_record_seq(input, &umi, &umi_qual, &layout, None)?;
+        // A read number of 0 leaves the header unchanged.
+        let edited = correct_read_nr(edited, (read_nr != 0).then_some(read_nr))?;
+        write_record(&edited, out)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn umi_transfer_options_default() -> UmiTransferOptions {
+    UmiTransferOptions {
+        struct_size: std::mem::size_of::<UmiTransferOptions>(),
+        position: UMI_TRANSFER_HEADER,
+        correct_numbers: false,
+        delim: ptr::null(),
+        header_template: ptr::null(),
+        on_existing_umi: UMI_TRANSFER_EXISTING_ERROR,
+        gzip: false,
+        compression_level: 0,
+        threads: 0,
+        force: false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn umi_transfer_files(
+    r1: *const c_char,
+    r2: *const c_char,
+    umi: *const c_char,
+    out1: *const c_char,
+    out2: *const c_char,
+    options: *const UmiTransferOptions,
+    records: *mut u64,
+) -> i32 {
+    status(|| {
+        let defaults = umi_transfer_options_default();
+        let options = options.as_ref().unwrap_or(&defaults);
+        if options.struct_size != defaults.struct_size {
+            return Err(invalid(format!(
+                "options->struct_size is {}, but must be {}. Initialize the options with umi_transfer_options_default()",
+                options.struct_size, defaults.struct_size
+            )));
+        }
+        let destination = match options.position {
+            UMI_TRANSFER_HEADER => UMIDestination::Header,
+            UMI_TRANSFER_INLINE => UMIDestination::Inline,
+            position => return Err(invalid(format!("{} is not a valid position", position))),
+        };
+
+        let mut transfer = UmiTransfer::new(
+            required_str(r1, "r1")?,
+            required_str(r2, "r2")?,
+            required_str(umi, "umi")?,
+        )
+        .destination(destination)
+        .correct_read_numbers(options.correct_numbers)
+        .delim(optional_str(options.delim, "delim")?.unwrap_or(":"))
+        .header_template(
+            optional_str(options.header_template, "header_template")?.unwrap_or(DEFAULT_TEMPLATE),
+        )
+        .on_existing_umi(existing_umi(options.on_existing_umi)?)
+        .gzip(options.gzip)
+        // A library can not prompt, so existing outputs are kept unless they may be overwritten.
+        .overwrite(if options.force {
+            OverwritePolicy::Always
+        } else {
+            OverwritePolicy::Never
+        });
+
+        // Settings of 0 and null pointers select the default.
+        if let Some(output) = optional_str(out1, "out1")? {
+            transfer = transfer.out(output);
+        }
+        if let Some(output) = optional_str(out2, "out2")? {
+            transfer = transfer.out2(output);
+        }
+        if options.compression_level != 0 {
+            transfer = transfer.compression_level(options.compression_level);
+        }
+        if options.threads != 0 {
+            transfer = transfer.threads(options.threads);
+        }
+
+        let summary = transfer.run()?;
+        if let Some(records) = records.as_mut() {
+            *records = summary.records;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn umi_transfer_buffer_free(buffer: UmiTransferBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn umi_transfer_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::TempDir;
+
+    const SEQDATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/seqdata");
+
+    fn last_error() -> String {
+        let message = umi_transfer_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Returns the text of a buffer and frees it.
+    fn take_buffer(buffer: &mut UmiTransferBuffer) -> String {
+        let buffer = std::mem::replace(
+            buffer,
+            UmiTransferBuffer {
+                data: ptr::null_mut(),
+                len: 0,
+            },
+        );
+        let text = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) };
+        let text = String::from_utf8(text.to_vec()).unwrap();
+        unsafe { umi_transfer_buffer_free(buffer) };
+        text
+    }
+
+    #[test]
+    fn test_record_header() {
+        let record = b"@READ1 1:N:0:2\nACGT\n+\nFFFF\n";
+        let mut out = UmiTransferBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        let status = unsafe {
+            umi_transfer_record_header(
+                record.as_ptr(),
+                record.len(),
+                b"GGCC".as_ptr(),
+                ptr::null(),
+                4,
+                c"_".as_ptr(),
+                ptr::null(),
+                1,
+                false,
+                UMI_TRANSFER_EXISTING_ERROR,
+                &mut out,
+            )
+        };
+        assert_eq!(status, UMI_TRANSFER_OK);
+        assert!(umi_transfer_last_error().is_null());
+        assert_eq!(
+            take_buffer(&mut out),
+            "@READ1_GGCC 1:N:0:2\nACGT\n+\nFFFF\n"
+        );
+
+        // The record already carries a UMI.
+        let record = b"@READ1:ACGT\nACGT\n+\nFFFF\n";
+        let status = unsafe {
+            umi_transfer_record_header(
+                record.as_ptr(),
+                record.len(),
+                b"GGCC".as_ptr(),
+                ptr::null(),
+                4,
+                ptr::null(),
+                ptr::null(),
+                1,
+                false,
+                UMI_TRANSFER_EXISTING_ERROR,
+                &mut out,
+            )
+        };
+        assert_eq!(status, EXIT_FAILURE);
+        assert!(last_error().contains("already contains a UMI"));
+    }
+
+    #[test]
+    fn test_record_inline() {
+        let record = b"@READ1 1:N:0:2\nACGT\n+\nFFFF\n";
+        let mut out = UmiTransferBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        let status = unsafe {
+            umi_transfer_record_inline(
+                record.as_ptr(),
+                record.len(),
+                b"GGCC".as_ptr(),
+                b"IIII".as_ptr(),
+                4,
+                UMI_TRANSFER_3P,
+                c"T".as_ptr(),
+                0,
+                2,
+                &mut out,
+            )
+        };
+        assert_eq!(status, UMI_TRANSFER_OK);
+        assert_eq!(
+            take_buffer(&mut out),
+            "@READ1 2:N:0:2\nACGTTGGCC\n+\nFFFFIIIII\n"
+        );
+
+        // A record without qualities is not valid FastQ.
+        let record = b"@READ1\nACGT\n";
+        let status = unsafe {
+            umi_transfer_record_inline(
+                record.as_ptr(),
+                record.len(),
+                b"GGCC".as_ptr(),
+                ptr::null(),
+                4,
+                UMI_TRANSFER_5P,
+                ptr::null(),
+                0,
+                0,
+                &mut out,
+            )
+        };
+        assert_eq!(status, 9);
+        assert!(last_error().contains("Record 1 of record buffer is not valid FastQ"));
+
+        let status = unsafe {
+            umi_transfer_record_inline(
+                ptr::null(),
+                10,
+                b"GGCC".as_ptr(),
+                ptr::null(),
+                4,
+                UMI_TRANSFER_5P,
+                ptr::null(),
+                0,
+                0,
+                &mut out,
+            )
+        };
+        assert_eq!(status, EXIT_USAGE);
+        assert_eq!(last_error(), "Invalid argument: record is null.");
+    }
+
+    #[test]
+    fn test_transfer_files() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let path = |name: &str| CString::new(format!("{}/{}", SEQDATA, name)).unwrap();
+        let output = |name: &str| {
+            CString::new(temp_dir.path().join(name).to_string_lossy().into_owned()).unwrap()
+        };
+        let mut options = umi_transfer_options_default();
+        options.threads = 1;
+        let mut records = 0;
+
+        let status = unsafe {
+            umi_transfer_files(
+                path("read1.fq").as_ptr(),
+                path("read2.fq").as_ptr(),
+                path("umi.fq").as_ptr(),
+                output("read1_out.fq").as_ptr(),
+                output("read2_out.fq").as_ptr(),
+                &options,
+                &mut records,
+            )
+        };
+        assert_eq!(status, UMI_TRANSFER_OK);
+        assert_eq!(records, 10);
+        assert!(temp_dir.path().join("read2_out.fq").exists());
+
+        // Existing outputs are an error without force, even if a terminal is attached.
+        let status = unsafe {
+            umi_transfer_files(
+                path("read1.fq").as_ptr(),
+                path("read2.fq").as_ptr(),
+                path("umi.fq").as_ptr(),
+                output("read1_out.fq").as_ptr(),
+                output("read2_out.fq").as_ptr(),
+                &options,
+                &mut records,
+            )
+        };
+        assert_eq!(status, 4);
+        assert!(last_error().contains("read1_out.fq exists, but must not be overwritten"));
+
+        options.force = true;
+        let status = unsafe {
+            umi_transfer_files(
+                path("read1.fq").as_ptr(),
+                path("read2.fq").as_ptr(),
+                path("umi.fq").as_ptr(),
+                output("read1_out.fq").as_ptr(),
+                output("read2_out.fq").as_ptr(),
+                &options,
+                &mut records,
+            )
+        };
+        assert_eq!(status, UMI_TRANSFER_OK);
+
+        // Options of another layout, e.g. not initialized with umi_transfer_options_default().
+        options.struct_size = 0;
+        let status = unsafe {
+            umi_transfer_files(
+                path("read1.fq").as_ptr(),
+                path("read2.fq").as_ptr(),
+                path("umi.fq").as_ptr(),
+                output("read1_out.fq").as_ptr(),
+                output("read2_out.fq").as_ptr(),
+                &options,
+                &mut records,
+            )
+        };
+        assert_eq!(status, EXIT_USAGE);
+        assert!(last_error().contains("options->struct_size is 0"));
+
+        let status = unsafe {
+            umi_transfer_files(
+                path("missing.fq").as_ptr(),
+                path("read2.fq").as_ptr(),
+                path("umi.fq").as_ptr(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, 3);
+        assert!(last_error().contains("missing.fq does not exist"));
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+}
@@ -0,0 +1,535 @@
+use bio::io::fastq::Record;
+use clap::ValueEnum;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use umi_transfer::file_io::OutputNaming;
+use umi_transfer::header_template::{HeaderTemplate, DEFAULT_TEMPLATE};
+use umi_transfer::read_editing::{
+    umi_to_record_header, umi_to_record_seq, ExistingUMI, InlineEnd, InlineLayout, InlineReads,
+    UMIDestination,
+};
+use umi_transfer::umi_source::{parse_umi_in_read, UmiInput, DEFAULT_UMI_QUAL};
+use umi_transfer::{OverwritePolicy, QualityEncoding, UmiTransfer};
+
+// Raised for all errors of the transfer, with the complete chain of causes as message.
+create_exception!(umi_transfer, UmiTransferError, PyException);
+
+fn to_py_err(error: anyhow::Error) -> PyErr {
+    UmiTransferError::new_err(format!("{:#}", error))
+}
+
+// Parses the value of an option like on the command line, e.g. 'header' or '5p'.
+fn parse_value<T: ValueEnum>(option: &str, value: &str) -> PyResult<T> {
+    T::from_str(value, true)
+        .map_err(|_| PyValueError::new_err(format!("{} is not a valid {}.", value, option)))
+}
+
+// Input files can be given as a single path or as a list of paths, e.g. lanes.
+#[derive(FromPyObject)]
+enum Paths {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+impl From<Paths> for Vec<PathBuf> {
+    fn from(paths: Paths) -> Self {
+        match paths {
+            Paths::One(path) => vec![path],
+            Paths::Many(paths) => paths,
+        }
+    }
+}
+
+// FastQ record as passed to and returned by the record editors.
+#[pyclass(name = "FastqRecord", get_all, set_all)]
+#[derive(Debug, Clone, PartialEq)]
+struct PyRecord {
+    id: String,
+    desc: Option<String>,
+    seq: String,
+    qual: String,
+}
+
+#[pymethods]
+impl PyRecord {
+    #[new]
+    #[pyo3(signature = (id, seq, qual, desc=None))]
+    fn new(id: String, seq: String, qual: String, desc: Option<String>) -> Self {
+        PyRecord {
+            id,
+            desc,
+            seq,
+            qual,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "FastqRecord(id={:?}, seq={:?}, qual={:?}, desc={:?})",
+            self.id, self.seq, self.qual, self.desc
+        )
+    }
+
+    fn __eq__(&self, other: PyRef<'_, PyRecord>) -> bool {
+        *self == *other
+    }
+}
+
+impl PyRecord {
+    fn to_record(&self) -> Record {
+        Record::with_attrs(
+            &self.id,
+            self.desc.as_deref(),
+            self.seq.as_bytes(),
+            self.qual.as_bytes(),
+        )
+    }
+
+    fn from_record(record: Record) -> Self {
+        PyRecord {
+            id: record.id().to_string(),
+            desc: record.desc().map(str::to_string),
+            seq: String::from_utf8_lossy(record.seq()).into_owned(),
+            qual: String::from_utf8_lossy(record.qual()).into_owned(),
+        }
+    }
+}
+
+// Qualities of a UMI without given qualities, as for UMIs from sources without qualities.
+fn umi_qual_or_default(umi: &str, umi_qual: Option<&str>) -> String {
+    umi_qual.map_or_else(
+        || char::from(DEFAULT_UMI_QUAL).to_string().repeat(umi.len()),
+        str::to_string,
+    )
+}
+
+// Runs `umi-transfer external` and returns its statistics as dict. The options correspond to the
+// command line options, and the GIL is released while the files are processed. A `progress`
+// callable is given the number of processed record pairs every `progress_every` records.
+#[pyfunction]
+#[pyo3(signature = (
+    r1,
+    r2,
+    umi=None,
+    *,
+    out=None,
+    out2=None,
+    out_umi=None,
+    outdir=None,
+    suffix=None,
+    name_template=None,
+    position="header",
+    correct_numbers=false,
+    delim=":",
+    header_template=None,
+    on_existing_umi="error",
+    inline_end="5p",
+    inline_reads="both",
+    spacer=None,
+    spacer_qual=None,
+    sample=None,
+    gzip=false,
+    compression_level=None,
+    threads=None,
+    force=false,
+    overwrite=None,
+    umi_table=None,
+    umi_in_read=None,
+    umi_from_index=false,
+    strict=false,
+    convert_quals_to=None,
+    progress=None,
+    progress_every=100_000,
+    verbose=false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn transfer_external<'py>(
+    py: Python<'py>,
+    r1: Paths,
+    r2: Paths,
+    umi: Option<Paths>,
+    out: Option<PathBuf>,
+    out2: Option<PathBuf>,
+    out_umi: Option<PathBuf>,
+    outdir: Option<PathBuf>,
+    suffix: Option<&str>,
+    name_template: Option<&str>,
+    position: &str,
+    correct_numbers: bool,
+    delim: &str,
+    header_template: Option<&str>,
+    on_existing_umi: &str,
+    inline_end: &str,
+    inline_reads: &str,
+    spacer: Option<String>,
+    spacer_qual: Option<char>,
+    sample: Option<String>,
+    gzip: bool,
+    compression_level: Option<u32>,
+    threads: Option<usize>,
+    force: bool,
+    overwrite: Option<&str>,
+    umi_table: Option<PathBuf>,
+    umi_in_read: Option<&str>,
+    umi_from_index: bool,
+    strict: bool,
+    convert_quals_to: Option<&str>,
+    progress: Option<Py<PyAny>>,
+    progress_every: u64,
+    verbose: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    // Exactly one source of UMIs must be given, as on the command line.
+    let umis = match (umi, umi_table, umi_in_read, umi_from_index) {
+        (Some(umi), None, None, false) => UmiInput::FastQ(umi.into()),
+        (None, Some(table), None, false) => UmiInput::Table(table),
+        (None, None, Some(segment), false) => {
+            let (read, length) = parse_umi_in_read(segment).map_err(PyValueError::new_err)?;
+            UmiInput::Read(read, length)
+        }
+        (None, None, None, true) => UmiInput::Index,
+        _ => {
+            return Err(PyValueError::new_err(
+                "Please specify exactly one of umi, umi_table, umi_in_read and umi_from_index.",
+            ))
+        }
+    };
+
+    let naming = OutputNaming::new(outdir, suffix, name_template).map_err(to_py_err)?;
+
+    let mut transfer = UmiTransfer::with_umis(r1.into(), r2.into(), umis)
+        .output_naming(naming)
+        .destination(parse_value::<UMIDestination>("position", position)?)
+        .correct_read_numbers(correct_numbers)
+        .delim(delim)
+        .header_template(header_template.unwrap_or(DEFAULT_TEMPLATE))
+        .on_existing_umi(parse_value::<ExistingUMI>(
+            "on_existing_umi",
+            on_existing_umi,
+        )?)
+        .inline_end(parse_value::<InlineEnd>("inline_end", inline_end)?)
+        .inline_reads(parse_value::<InlineReads>("inline_reads", inline_reads)?)
+        .gzip(gzip)
+        // Python can not answer a prompt while the GIL is released, so existing outputs are kept
+        // unless they may be overwritten.
+        .overwrite(match overwrite {
+            Some(overwrite) => parse_value::<OverwritePolicy>("overwrite", overwrite)?,
+            None if force => OverwritePolicy::Always,
+            None => OverwritePolicy::Never,
+        })
+        .strict(strict)
+        .verbose(verbose);
+
+    // Settings without default value are only passed on if they were given.
+    if let Some(output) = out {
+        transfer = transfer.out(output);
+    }
+    if let Some(output) = out2 {
+        transfer = transfer.out2(output);
+    }
+    if let Some(output) = out_umi {
+        transfer = transfer.out_umi(output);
+    }
+    if let Some(spacer) = spacer {
+        transfer = transfer.spacer(spacer);
+    }
+    if let Some(spacer_qual) = spacer_qual {
+        transfer = transfer.spacer_qual(spacer_qual);
+    }
+    if let Some(sample) = sample {
+        transfer = transfer.sample(sample);
+    }
+    if let Some(level) = compression_level {
+        transfer = transfer.compression_level(level);
+    }
+    if let Some(threads) = threads {
+        transfer = transfer.threads(threads);
+    }
+    if let Some(encoding) = convert_quals_to {
+        transfer = transfer.convert_quals_to(parse_value::<QualityEncoding>(
+            "convert_quals_to",
+            encoding,
+        )?);
+    }
+
+    // The callback is called with the GIL, which is released again while the next records are
+    // processed. An exception of the callback ends the run and is raised unchanged.
+    let callback_error: Arc<Mutex<Option<PyErr>>> = Arc::default();
+    if let Some(callback) = progress {
+        let callback_error = Arc::clone(&callback_error);
+        transfer = transfer.progress(progress_every, move |records| {
+            Python::attach(|py| callback.call1(py, (records,)).map(drop)).map_err(|error| {
+                let message = format!("Progress callback failed: {}", error);
+                *callback_error.lock().unwrap() = Some(error);
+                anyhow::anyhow!(message)
+            })
+        });
+    }
+
+    let start = Instant::now();
+    let summary = py.detach(|| transfer.run()).map_err(|error| {
+        callback_error
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| to_py_err(error))
+    })?;
+
+    let stats = PyDict::new(py);
+    stats.set_item("records", summary.records)?;
+    stats.set_item("resumed_after", summary.resumed_after)?;
+    stats.set_item("output1", summary.output1.to_string_lossy())?;
+    stats.set_item("output2", summary.output2.to_string_lossy())?;
+    stats.set_item(
+        "output_umi",
+        summary
+            .output_umi
+            .map(|path| path.to_string_lossy().into_owned()),
+    )?;
+    stats.set_item("output1_bytes", summary.output1_bytes)?;
+    stats.set_item("output2_bytes", summary.output2_bytes)?;
+    stats.set_item("output_umi_bytes", summary.output_umi_bytes)?;
+    stats.set_item("seconds", start.elapsed().as_secs_f64())?;
+    Ok(stats)
+}
+
+// Adds a UMI to the header of a single record, like `--position header`.
+#[pyfunction]
+#[pyo3(signature = (
+    record,
+    umi,
+    umi_qual=None,
+    *,
+    read_nr=1,
+    delim=":",
+    header_template=None,
+    sample=None,
+    correct_numbers=false,
+    on_existing_umi="error",
+))]
+#[allow(clippy::too_many_arguments)]
+fn add_umi_to_header(
+    record: PyRef<'_, PyRecord>,
+    umi: &str,
+    umi_qual: Option<&str>,
+    read_nr: u8,
+    delim: &str,
+    header_template: Option<&str>,
+    sample: Option<&str>,
+    correct_numbers: bool,
+    on_existing_umi: &str,
+) -> PyResult<PyRecord> {
+    let template = HeaderTemplate::new(header_template.unwrap_or(DEFAULT_TEMPLATE), delim, sample)
+        .map_err(to_py_err)?;
+    let umi_qual = umi_qual_or_default(umi, umi_qual);
+    umi_to_record_header(
+        record.to_record(),
+        umi.as_bytes(),
+        umi_qual.as_bytes(),
+        &template,
+        read_nr,
+        correct_numbers,
+        &parse_value::<ExistingUMI>("on_existing_umi", on_existing_umi)?,
+    )
+    .map(PyRecord::from_record)
+    .map_err(to_py_err)
+}
+
+// Adds a UMI to the sequence of a single record, like `--position inline`. If a read number is
+// given, it is also corrected in the header.
+#[pyfunction]
+#[pyo3(signature = (
+    record,
+    umi,
+    umi_qual=None,
+    *,
+    inline_end="5p",
+    spacer=None,
+    spacer_qual=None,
+    read_nr=None,
+))]
+fn add_umi_inline(
+    record: PyRef<'_, PyRecord>,
+    umi: &str,
+    umi_qual: Option<&str>,
+    inline_end: &str,
+    spacer: Option<&str>,
+    spacer_qual: Option<char>,
+    read_nr: Option<u8>,
+) -> PyResult<PyRecord> {
+    let layout = InlineLayout::new(
+        parse_value::<InlineEnd>("inline_end", inline_end)?,
+        InlineReads::Both,
+        spacer,
+        spacer_qual,
+    )
+    .map_err(to_py_err)?;
+    let umi_qual = umi_qual_or_default(umi, umi_qual);
+    umi_to_record_seq(
+        record.to_record(),
+        umi.as_bytes(),
+        umi_qual.as_bytes(),
+        &layout,
+        read_nr,
+    )
+    .map(PyRecord::from_record)
+    .map_err(to_py_err)
+}
+
+#[pymodule]
+#[pyo3(name = "umi_transfer")]
+fn umi_transfer_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("UmiTransferError", m.py().get_type::<UmiTransferError>())?;
+    m.add_class::<PyRecord>()?;
+    m.add_function(wrap_pyfunction!(transfer_external, m)?)?;
+    m.add_function(wrap_pyfunction!(add_umi_to_header, m)?)?;
+    m.add_function(wrap_pyfunction!(add_umi_inline, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::TempDir;
+    use std::ffi::CString;
+    use std::path::Path;
+
+    const SEQDATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/seqdata");
+
+    // Runs Python code with the module imported as `umi_transfer`, the test data in `seqdata` and a
+    // directory for outputs in `out_dir`.
+    fn run_python(code: &str, out_dir: &Path) -> PyResult<()> {
+        Python::attach(|py| {
+            let module = PyModule::new(py, "umi_transfer")?;
+            umi_transfer_python(&module)?;
+            let globals = PyDict::new(py);
+            globals.set_item("umi_transfer", module)?;
+            globals.set_item("seqdata", SEQDATA)?;
+            globals.set_item("out_dir", out_dir.to_string_lossy())?;
+            let code = CString::new(code).expect("Python code must not contain NUL bytes");
+            py.run(&code, Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn test_transfer_external() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        run_python(
+            r#"
+stats = umi_transfer.transfer_external(
+    f"{seqdata}/read1.fq",
+    [f"{seqdata}/read2.fq"],
+    f"{seqdata}/umi.fq",
+    out=f"{out_dir}/read1_out.fq",
+    out2=f"{out_dir}/read2_out.fq",
+    threads=1,
+)
+assert stats["records"] == 10, stats
+assert stats["resumed_after"] == 0
+assert stats["output1"] == f"{out_dir}/read1_out.fq"
+assert stats["output_umi"] is None
+assert stats["output_umi_bytes"] is None
+assert stats["output1_bytes"] > 0
+with open(stats["output1"]) as output:
+    assert output.readline().startswith("@SCILIFELAB:500:NGISTLM:1:1101:19994:1016:CCTGAGACC ")
+
+try:
+    umi_transfer.transfer_external(
+        f"{seqdata}/read1.fq", f"{seqdata}/read2.fq", f"{seqdata}/umi_shuffled.fq",
+        out=f"{out_dir}/read1_out.fq", out2=f"{out_dir}/read2_out.fq", force=True,
+    )
+    raise AssertionError("mismatching IDs were not reported")
+except umi_transfer.UmiTransferError as error:
+    assert "IDs of UMI and read records mismatch" in str(error), error
+
+reported = []
+stats = umi_transfer.transfer_external(
+    f"{seqdata}/read1.fq", f"{seqdata}/read2.fq", f"{seqdata}/umi.fq",
+    out=f"{out_dir}/read1_progress.fq", out2=f"{out_dir}/read2_progress.fq",
+    progress=reported.append, progress_every=4,
+)
+assert reported == [4, 8], reported
+
+class Cancelled(Exception):
+    pass
+
+def cancel(records):
+    raise Cancelled(records)
+
+try:
+    umi_transfer.transfer_external(
+        f"{seqdata}/read1.fq", f"{seqdata}/read2.fq", f"{seqdata}/umi.fq",
+        out=f"{out_dir}/read1_cancelled.fq", out2=f"{out_dir}/read2_cancelled.fq",
+        progress=cancel, progress_every=4,
+    )
+    raise AssertionError("the exception of the callback was not raised")
+except Cancelled as error:
+    assert error.args == (4,), error
+
+try:
+    umi_transfer.transfer_external(
+        f"{seqdata}/read1.fq", f"{seqdata}/read2.fq", f"{seqdata}/umi.fq",
+        out=f"{out_dir}/read1_out.fq", out2=f"{out_dir}/read2_out.fq",
+    )
+    raise AssertionError("the existing output was overwritten")
+except umi_transfer.UmiTransferError as error:
+    assert "read1_out.fq exists, but must not be overwritten" in str(error), error
+
+try:
+    umi_transfer.transfer_external([], [], [])
+    raise AssertionError("missing inputs were not reported")
+except umi_transfer.UmiTransferError as error:
+    assert "No input files were given" in str(error), error
+
+try:
+    umi_transfer.transfer_external(f"{seqdata}/read1.fq", f"{seqdata}/read2.fq")
+    raise AssertionError("missing UMIs were not reported")
+except ValueError as error:
+    assert "exactly one of umi" in str(error), error
+"#,
+            temp_dir.path(),
+        )
+        .unwrap();
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_record_editors() {
+        run_python(
+            r#"
+record = umi_transfer.FastqRecord("READ1", "ACGT", "FFFF", desc="1:N:0:2")
+
+edited = umi_transfer.add_umi_to_header(record, "GGCC", read_nr=1, delim="_")
+assert edited.id == "READ1_GGCC", edited
+assert edited.desc == "1:N:0:2"
+assert edited.seq == "ACGT"
+
+edited = umi_transfer.add_umi_inline(record, "GGCC", "IIII", inline_end="3p", spacer="T")
+assert edited == umi_transfer.FastqRecord("READ1", "ACGTTGGCC", "FFFFIIIII", "1:N:0:2"), edited
+
+try:
+    umi_transfer.add_umi_to_header(umi_transfer.FastqRecord("READ1:ACGT", "A", "F"), "GGCC")
+    raise AssertionError("existing UMI was not reported")
+except umi_transfer.UmiTransferError as error:
+    assert "already" in str(error), error
+
+try:
+    umi_transfer.add_umi_inline(record, "GGCC", inline_end="middle")
+    raise AssertionError("invalid option was not reported")
+except ValueError as error:
+    assert "middle is not a valid inline_end" in str(error), error
+"#,
+            &std::env::temp_dir(),
+        )
+        .unwrap();
+    }
+}
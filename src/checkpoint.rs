@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::umi_errors::RuntimeErrors;
+
+// Progress of a run, saved next to the first output, so that an interrupted run can be resumed.
+// The outputs are valid up to the recorded lengths and contain exactly `records` records.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub records: u64,
+    // Interval of the checkpoints, which a resumed run continues to use. Missing in older sidecars.
+    #[serde(default)]
+    pub every: Option<u64>,
+    pub outputs: Vec<CheckpointOutput>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointOutput {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+// Path of the sidecar file for an output, e.g. `read1_with_UMIs.fq.gz.checkpoint`.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".checkpoint");
+    PathBuf::from(path)
+}
+
+impl Checkpoint {
+    pub fn new(records: u64, every: Option<u64>, paths: &[PathBuf], lengths: Vec<u64>) -> Self {
+        Checkpoint {
+            records,
+            every,
+            outputs: paths
+                .iter()
+                .zip(lengths)
+                .map(|(path, length)| CheckpointOutput {
+                    path: path.clone(),
+                    length,
+                })
+                .collect(),
+        }
+    }
+
+    // The sidecar is replaced in one step, so that an interruption never leaves a partial checkpoint.
+    pub fn save(&self, sidecar: &Path) -> Result<()> {
+        let content = toml::to_string(self)?;
+        let mut temp_path = sidecar.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, content).map_err(|source| {
+            anyhow!(RuntimeErrors::OutputNotWriteable {
+                path: sidecar.into(),
+                source
+            })
+        })?;
+        fs::rename(&temp_path, sidecar)?;
+        Ok(())
+    }
+
+    pub fn load(sidecar: &Path) -> Result<Self> {
+        let content = fs::read_to_string(sidecar).map_err(|_e| {
+            anyhow!(RuntimeErrors::CheckpointInvalid(
+                sidecar.into(),
+                "no checkpoint was found, the run must be started without --resume".to_string()
+            ))
+        })?;
+        toml::from_str(&content).map_err(|e| {
+            anyhow!(RuntimeErrors::CheckpointInvalid(
+                sidecar.into(),
+                e.to_string().trim().to_string()
+            ))
+        })
+    }
+
+    // Returns the recorded lengths, if the checkpoint belongs to the given outputs.
+    pub fn lengths_for(&self, sidecar: &Path, paths: &[PathBuf]) -> Result<Vec<u64>> {
+        let recorded: Vec<&PathBuf> = self.outputs.iter().map(|output| &output.path).collect();
+        if recorded != paths.iter().collect::<Vec<_>>() {
+            return Err(anyhow!(RuntimeErrors::CheckpointInvalid(
+                sidecar.into(),
+                format!(
+                    "it was written for the outputs {}",
+                    recorded
+                        .iter()
+                        .map(|path| path.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            )));
+        }
+        Ok(self.outputs.iter().map(|output| output.length).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("out/read1_with_UMIs.fq.gz")),
+            PathBuf::from("out/read1_with_UMIs.fq.gz.checkpoint")
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let sidecar = temp_dir.path().join("read1_with_UMIs.fq.checkpoint");
+        let paths = vec![
+            PathBuf::from("read1_with_UMIs.fq"),
+            PathBuf::from("read2_with_UMIs.fq"),
+        ];
+
+        let checkpoint = Checkpoint::new(1000, Some(500), &paths, vec![81920, 80000]);
+        checkpoint.save(&sidecar).unwrap();
+
+        let loaded = Checkpoint::load(&sidecar).unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(
+            loaded.lengths_for(&sidecar, &paths).unwrap(),
+            vec![81920, 80000]
+        );
+
+        // Sidecars of runs without an interval, e.g. from older versions, can be read as well.
+        let content = "records = 4\n\n[[outputs]]\npath = \"read1_with_UMIs.fq\"\nlength = 10\n";
+        std::fs::write(&sidecar, content).unwrap();
+        assert_eq!(Checkpoint::load(&sidecar).unwrap().every, None);
+
+        let result = loaded.lengths_for(&sidecar, &paths[..1]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("it was written for the outputs read1_with_UMIs.fq, read2_with_UMIs.fq"));
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_checkpoint_missing() {
+        let result = Checkpoint::load(Path::new("missing.fq.checkpoint"));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no checkpoint was found"));
+    }
+}
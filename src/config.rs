@@ -0,0 +1,353 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::umi_external::TransferOptions;
+use umi_transfer::auxiliary::threads_available;
+use umi_transfer::file_io::OverwritePolicy;
+use umi_transfer::header_template::DEFAULT_TEMPLATE;
+use umi_transfer::read_editing::{ExistingUMI, InlineEnd, InlineReads, UMIDestination};
+use umi_transfer::umi_errors::RuntimeErrors;
+use umi_transfer::QualityEncoding;
+
+#[derive(Debug, Parser)]
+pub struct OptsConfig {
+    #[clap(subcommand)]
+    cmd: ConfigCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum ConfigCommand {
+    /// Print the settings that result from the configuration file, environment variables and command line.
+    Show(TransferOptions),
+}
+
+// Settings that can be given in a configuration file. The keys are named like the long command line options.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub position: Option<UMIDestination>,
+    pub correct_numbers: Option<bool>,
+    pub gzip: Option<bool>,
+    pub compression_level: Option<u32>,
+    pub threads: Option<usize>,
+    pub force: Option<bool>,
+    pub overwrite: Option<OverwritePolicy>,
+    pub strict: Option<bool>,
+    pub convert_quals_to: Option<QualityEncoding>,
+    pub delim: Option<String>,
+    pub header_template: Option<String>,
+    pub on_existing_umi: Option<ExistingUMI>,
+    pub inline_end: Option<InlineEnd>,
+    pub inline_reads: Option<InlineReads>,
+    pub spacer: Option<String>,
+    pub spacer_qual: Option<char>,
+}
+
+// Parses the content of a configuration file. Files ending in .yaml or .yml are parsed as YAML, all others as TOML.
+pub fn parse_config(content: &str, path: &Path) -> Result<ConfigFile> {
+    let is_yaml = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+
+    let parsed = if is_yaml {
+        // An empty YAML document is null, not an empty mapping.
+        if content.trim().is_empty() {
+            Ok(ConfigFile::default())
+        } else {
+            serde_yaml::from_str(content).map_err(|e| e.to_string())
+        }
+    } else {
+        toml::from_str(content).map_err(|e| e.to_string())
+    };
+
+    parsed.map_err(|reason| {
+        anyhow!(RuntimeErrors::ConfigInvalid(
+            path.into(),
+            reason.trim().to_string()
+        ))
+    })
+}
+
+pub fn read_config(path: &Path) -> Result<ConfigFile> {
+    let content = fs::read_to_string(path).map_err(|source| {
+        anyhow!(RuntimeErrors::FileNotFound {
+            path: path.into(),
+            source
+        })
+    })?;
+    parse_config(&content, path)
+}
+
+// Settings given on the command line or as environment variables take precedence over the configuration file.
+// This includes flags, which can be switched off with e.g. `--gzip=false`.
+pub fn merge_config(settings: TransferOptions, config: ConfigFile) -> TransferOptions {
+    TransferOptions {
+        config: settings.config,
+        target_position: settings.target_position.or(config.position),
+        edit_nr: settings.edit_nr.or(config.correct_numbers),
+        gzip: settings.gzip.or(config.gzip),
+        compression_level: settings.compression_level.or(config.compression_level),
+        num_threads: settings.num_threads.or(config.threads),
+        force: settings.force.or(config.force),
+        // --force on the command line also takes precedence over an overwrite policy in the file.
+        overwrite: settings
+            .overwrite
+            .or(config.overwrite.filter(|_| settings.force != Some(true))),
+        strict: settings.strict.or(config.strict),
+        convert_quals_to: settings.convert_quals_to.or(config.convert_quals_to),
+        delim: settings.delim.or(config.delim),
+        header_template: settings.header_template.or(config.header_template),
+        on_existing_umi: settings.on_existing_umi.or(config.on_existing_umi),
+        inline_end: settings.inline_end.or(config.inline_end),
+        inline_reads: settings.inline_reads.or(config.inline_reads),
+        spacer: settings.spacer.or(config.spacer),
+        spacer_qual: settings.spacer_qual.or(config.spacer_qual),
+    }
+}
+
+// Reads the configuration file, if one was given, and fills in all settings not given otherwise.
+pub fn apply_config(settings: TransferOptions) -> Result<TransferOptions> {
+    let Some(path) = settings.config.clone() else {
+        return Ok(settings);
+    };
+    let config = read_config(&path).with_context(|| {
+        format!(
+            "Failed to read the configuration file {}",
+            &path.to_string_lossy()
+        )
+    })?;
+    Ok(merge_config(settings, config))
+}
+
+// Name of a setting's value as it is written on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+pub fn run(args: OptsConfig) -> Result<i32> {
+    match args.cmd {
+        ConfigCommand::Show(settings) => {
+            let settings = apply_config(settings)?;
+            let position = value_name(
+                settings
+                    .target_position
+                    .clone()
+                    .unwrap_or(UMIDestination::Header),
+            );
+
+            println!("Effective settings:");
+            if let Some(path) = &settings.config {
+                println!("  config:            {}", path.display());
+            }
+            println!("  position:          {}", position);
+            println!("  correct_numbers:   {}", settings.edit_nr());
+            println!("  gzip:              {}", settings.gzip());
+            println!(
+                "  compression_level: {}",
+                settings
+                    .compression_level
+                    .map_or("default".to_string(), |l| l.clamp(1, 9).to_string())
+            );
+            println!(
+                "  threads:           {}",
+                settings.num_threads.unwrap_or_else(threads_available)
+            );
+            println!(
+                "  overwrite:         {}",
+                value_name(settings.overwrite_policy())
+            );
+            println!("  strict:            {}", settings.strict());
+            println!(
+                "  convert_quals_to:  {}",
+                settings
+                    .convert_quals_to
+                    .map_or("none".to_string(), value_name)
+            );
+            println!(
+                "  delim:             \"{}\"",
+                settings.delim.as_deref().unwrap_or(":")
+            );
+            println!(
+                "  header_template:   \"{}\"",
+                settings
+                    .header_template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_TEMPLATE)
+            );
+            println!(
+                "  on_existing_umi:   {}",
+                value_name(settings.on_existing_umi.unwrap_or(ExistingUMI::Error))
+            );
+            println!(
+                "  inline_end:        {}",
+                value_name(settings.inline_end.unwrap_or(InlineEnd::FivePrime))
+            );
+            println!(
+                "  inline_reads:      {}",
+                value_name(settings.inline_reads.unwrap_or(InlineReads::Both))
+            );
+            println!(
+                "  spacer:            \"{}\"",
+                settings.spacer.as_deref().unwrap_or_default()
+            );
+            println!(
+                "  spacer_qual:       '{}'",
+                settings.spacer_qual.unwrap_or('I')
+            );
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    fn cli_settings() -> TransferOptions {
+        TransferOptions {
+            config: Some(PathBuf::from("umi-transfer.toml")),
+            target_position: None,
+            edit_nr: None,
+            gzip: None,
+            compression_level: Some(9),
+            num_threads: None,
+            force: None,
+            overwrite: None,
+            strict: None,
+            convert_quals_to: None,
+            delim: None,
+            header_template: None,
+            on_existing_umi: None,
+            inline_end: None,
+            inline_reads: None,
+            spacer: None,
+            spacer_qual: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_config_toml() {
+        let content = "position = \"inline\"\ngzip = true\ncompression_level = 5\ndelim = \"_\"\n";
+        let config = parse_config(content, Path::new("umi-transfer.toml")).unwrap();
+        assert_eq!(
+            config,
+            ConfigFile {
+                position: Some(UMIDestination::Inline),
+                gzip: Some(true),
+                compression_level: Some(5),
+                delim: Some("_".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_config_yaml() {
+        let content = "position: header\ncorrect_numbers: true\nthreads: 9\n";
+        let config = parse_config(content, Path::new("umi-transfer.yml")).unwrap();
+        assert_eq!(
+            config,
+            ConfigFile {
+                position: Some(UMIDestination::Header),
+                correct_numbers: Some(true),
+                threads: Some(9),
+                ..Default::default()
+            }
+        );
+
+        let config = parse_config("", Path::new("umi-transfer.yaml")).unwrap();
+        assert_eq!(config, ConfigFile::default());
+    }
+
+    #[test]
+    fn test_parse_config_rejects_unknown_settings() {
+        let result = parse_config("compression = 5\n", Path::new("umi-transfer.toml"));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Configuration file umi-transfer.toml is invalid"));
+
+        let result = parse_config("position: middle\n", Path::new("umi-transfer.yaml"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_merge_config_prefers_command_line() {
+        let config = ConfigFile {
+            position: Some(UMIDestination::Inline),
+            gzip: Some(true),
+            compression_level: Some(5),
+            threads: Some(4),
+            ..Default::default()
+        };
+
+        let settings = merge_config(cli_settings(), config);
+        assert_eq!(settings.target_position, Some(UMIDestination::Inline));
+        assert!(settings.gzip());
+        assert!(!settings.force());
+        assert_eq!(settings.compression_level, Some(9));
+        assert_eq!(settings.num_threads, Some(4));
+        assert_eq!(settings.delim, None);
+    }
+
+    #[test]
+    fn test_merge_config_switches_off_flags() {
+        let config = ConfigFile {
+            correct_numbers: Some(true),
+            gzip: Some(true),
+            force: Some(true),
+            strict: Some(true),
+            ..Default::default()
+        };
+        let cli = TransferOptions {
+            edit_nr: Some(false),
+            gzip: Some(false),
+            force: Some(false),
+            strict: Some(false),
+            ..cli_settings()
+        };
+
+        let settings = merge_config(cli, config);
+        assert!(!settings.edit_nr());
+        assert!(!settings.gzip());
+        assert!(!settings.force());
+        assert!(!settings.strict());
+        assert_eq!(settings.overwrite_policy(), OverwritePolicy::Prompt);
+    }
+
+    #[test]
+    fn test_merge_config_overwrite_policy() {
+        let config = ConfigFile {
+            overwrite: Some(OverwritePolicy::Backup),
+            ..Default::default()
+        };
+        let settings = merge_config(cli_settings(), config);
+        assert_eq!(settings.overwrite_policy(), OverwritePolicy::Backup);
+
+        // --force on the command line replaces the policy of the configuration file.
+        let config = ConfigFile {
+            overwrite: Some(OverwritePolicy::Never),
+            ..Default::default()
+        };
+        let cli = TransferOptions {
+            force: Some(true),
+            ..cli_settings()
+        };
+        let settings = merge_config(cli, config);
+        assert_eq!(settings.overwrite_policy(), OverwritePolicy::Always);
+
+        assert_eq!(
+            merge_config(cli_settings(), ConfigFile::default()).overwrite_policy(),
+            OverwritePolicy::Prompt
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use super::umi_errors::RuntimeErrors;
-use anyhow::{anyhow, Context, Result};
-use bio::io::fastq::{Reader as FastqReader, Record, Writer as FastqWriter};
+use anyhow::{anyhow, Result};
+use bio::io::fastq::{Reader as FastqReader, Record};
 use dialoguer::{theme::ColorfulTheme, Confirm};
 use file_format::FileFormat;
-use gzp::{deflate::Gzip, par::compress::Compression, ZBuilder, ZWriter};
-use regex::Regex;
-use std::{fs, fs::File, io::BufWriter, path::Path, path::PathBuf};
+use gzp::{deflate::Gzip, par::compress::Compression, GzpError, ZBuilder, ZWriter};
+use regex::{Captures, Regex};
+use std::io::{BufWriter, IsTerminal, Seek, SeekFrom, Write};
+use std::{fs, fs::File, path::Path, path::PathBuf};
 
 ////////////////////////////////////////////////////////////////
 //  READ INPUT FILE
@@ -29,72 +30,443 @@ impl std::io::Read for InputFile {
 
 // Read input file to Reader. Automatically scans if input is compressed with file-format crate.
 pub fn read_fastq(path: &PathBuf) -> Result<bio::io::fastq::Reader<std::io::BufReader<InputFile>>> {
-    fs::metadata(path).map_err(|_e| anyhow!(RuntimeErrors::FileNotFound(Some(path.into()))))?;
+    fs::metadata(path).map_err(|source| {
+        anyhow!(RuntimeErrors::FileNotFound {
+            path: path.into(),
+            source
+        })
+    })?;
 
-    let format = FileFormat::from_file(path).context("Failed to determine file format")?;
+    let not_readable = |source| {
+        anyhow!(RuntimeErrors::InputNotReadable {
+            path: path.into(),
+            source
+        })
+    };
+    let format = FileFormat::from_file(path).map_err(not_readable)?;
+    let file = File::open(path).map_err(not_readable)?;
     let reader: InputFile = match format {
-        FileFormat::Gzip => {
-            let file = File::open(path)
-                .map(std::io::BufReader::new)
-                .with_context(|| format!("Failed to open file: {:?}", path))?;
-            InputFile::Compressed(Box::new(flate2::bufread::MultiGzDecoder::new(file)))
-        }
-        _ => {
-            let file =
-                File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
-            InputFile::Plain(std::io::BufReader::new(file))
-        }
+        FileFormat::Gzip => InputFile::Compressed(Box::new(flate2::bufread::MultiGzDecoder::new(
+            std::io::BufReader::new(file),
+        ))),
+        _ => InputFile::Plain(std::io::BufReader::new(file)),
     };
 
     Ok(FastqReader::new(reader))
 }
 
+// Turns an error of the FastQ parser into an error that names the file and the record. A file
+// that ends within a gzip block or within a record was not copied completely, so it is reported
+// as truncated.
+fn record_error(path: &Path, record: usize, error: bio::io::fastq::Error) -> anyhow::Error {
+    match error {
+        bio::io::fastq::Error::IncompleteRecord => anyhow!(RuntimeErrors::TruncatedInput(
+            path.display().to_string(),
+            record - 1
+        )),
+        bio::io::fastq::Error::ReadError(source)
+            if source.kind() == std::io::ErrorKind::UnexpectedEof =>
+        {
+            anyhow!(RuntimeErrors::TruncatedInput(
+                path.display().to_string(),
+                record - 1
+            ))
+        }
+        bio::io::fastq::Error::ReadError(source) => anyhow!(RuntimeErrors::InputNotReadable {
+            path: path.into(),
+            source
+        }),
+        error => anyhow!(RuntimeErrors::InvalidFastQ {
+            path: path.into(),
+            record,
+            source: error
+        }),
+    }
+}
+
+// Nucleotide codes of the IUPAC alphabet, which are accepted in sequences with --strict.
+const IUPAC_BASES: &[u8] = b"ACGTURYSWKMBDHVN";
+
+// Checks the parts of a record that the FastQ parser does not, but downstream tools rely on.
+// Qualities are accepted in the printable range of Phred+33 and Phred+64 encodings.
+pub fn check_record(record: &Record) -> std::result::Result<(), String> {
+    if record.id().is_empty() {
+        return Err("the read name is empty".into());
+    }
+    if record.seq().len() != record.qual().len() {
+        return Err(format!(
+            "{} bases, but {} quality scores",
+            record.seq().len(),
+            record.qual().len()
+        ));
+    }
+    if let Some(base) = record
+        .seq()
+        .iter()
+        .find(|base| !IUPAC_BASES.contains(&base.to_ascii_uppercase()))
+    {
+        return Err(format!(
+            "'{}' is not a nucleotide code",
+            base.escape_ascii()
+        ));
+    }
+    if let Some(qual) = record
+        .qual()
+        .iter()
+        .find(|qual| !(b'!'..=b'~').contains(*qual))
+    {
+        return Err(format!(
+            "'{}' is not a quality character",
+            qual.escape_ascii()
+        ));
+    }
+    Ok(())
+}
+
+// Reads several FastQ files one after another as if they were a single file, e.g. the lanes of a sample.
+// All files are opened upfront, so that missing files are reported before any records are processed.
+// With `strict`, every record is checked with check_record().
+pub fn read_fastqs(
+    paths: &[PathBuf],
+    strict: bool,
+) -> Result<impl Iterator<Item = Result<Record>>> {
+    let readers = paths
+        .iter()
+        .map(|path| read_fastq(path).map(|reader| (path.clone(), reader)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(readers.into_iter().flat_map(move |(path, reader)| {
+        reader.records().enumerate().map(move |(index, record)| {
+            let record = record.map_err(|e| record_error(&path, index + 1, e))?;
+            if strict {
+                check_record(&record).map_err(|reason| {
+                    anyhow!(RuntimeErrors::MalformedRecord {
+                        path: path.clone(),
+                        record: index + 1,
+                        reason
+                    })
+                })?;
+            }
+            Ok(record)
+        })
+    }))
+}
+
 ////////////////////////////////////////////////////////////////
 // WRITE OUTPUT FILE
 ////////////////////////////////////////////////////////////////
 
-// Enum for the two accepted output formats, '.fastq' and '.fastq.gz'
-pub enum OutputFile {
-    Plain(FastqWriter<File>),
-    Compressed(FastqWriter<Box<dyn ZWriter<File>>>),
+// Settings of the parallel gzip compression, which are kept to start a new gzip member at a checkpoint.
+#[derive(Debug, Clone, Copy)]
+pub struct GzipSettings {
+    num_threads: usize,
+    compression_level: Option<u32>,
+    pin_at: Option<usize>,
 }
 
-impl OutputFile {
-    pub fn write_record(&mut self, record: Record) -> Result<()> {
-        match self {
-            OutputFile::Plain(writer) => writer
-                .write(record.id(), record.desc(), record.seq(), record.qual())
-                .map_err(|_| anyhow!(RuntimeErrors::ReadWriteError(record))),
-            OutputFile::Compressed(writer) => writer
-                .write(record.id(), record.desc(), record.seq(), record.qual())
-                .map_err(|_| anyhow!(RuntimeErrors::ReadWriteError(record))),
+impl GzipSettings {
+    fn writer(&self, file: File) -> BufWriter<Box<dyn ZWriter<File>>> {
+        let writer = ZBuilder::<Gzip, _>::new()
+            .num_threads(self.num_threads)
+            .compression_level(
+                self.compression_level
+                    .map_or_else(Default::default, |l| Compression::new((l).clamp(1, 9))),
+            )
+            .pin_threads(self.pin_at)
+            .from_writer(file);
+        BufWriter::new(writer)
+    }
+}
+
+// Destination of the processed records. New output formats or side outputs implement this trait
+// and are returned by create_writer(), so that the transfer loop does not need to know about them.
+pub trait RecordSink {
+    fn write_record(&mut self, record: &Record) -> Result<()>;
+
+    // Writes the records of a read pair one after the other, i.e. as interleaved FastQ.
+    fn write_pair(&mut self, r1: &Record, r2: &Record) -> Result<()> {
+        self.write_record(r1)?;
+        self.write_record(r2)
+    }
+
+    // Writes all buffered records and returns the length of the output, to which it can be
+    // truncated when a run is resumed.
+    fn checkpoint(&mut self) -> Result<u64>;
+
+    // Writes all buffered records and completes the output. Errors are only reported here, e.g. on
+    // a full disk, as they are lost if the sink is dropped.
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+
+    // Number of uncompressed FastQ bytes written by this sink.
+    fn bytes_written(&self) -> u64;
+}
+
+// Writes a record in the same format as bio::io::fastq::Writer and returns the number of bytes.
+// The writer is not used, because it does not give access to the compressor, which needs to be
+// finished at checkpoints.
+fn write_fastq<W: Write>(writer: &mut W, record: &Record) -> std::io::Result<u64> {
+    let mut length = record.id().len() + record.seq().len() + record.qual().len() + 6;
+    writer.write_all(b"@")?;
+    writer.write_all(record.id().as_bytes())?;
+    if let Some(desc) = record.desc() {
+        writer.write_all(b" ")?;
+        writer.write_all(desc.as_bytes())?;
+        length += desc.len() + 1;
+    }
+    writer.write_all(b"\n")?;
+    writer.write_all(record.seq())?;
+    writer.write_all(b"\n+\n")?;
+    writer.write_all(record.qual())?;
+    writer.write_all(b"\n")?;
+    Ok(length as u64)
+}
+
+fn write_error(record: &Record, source: std::io::Error) -> anyhow::Error {
+    anyhow!(RuntimeErrors::ReadWriteError {
+        id: record.id().to_string(),
+        source
+    })
+}
+
+// Devices and FIFOs can not be synced.
+fn sync_file(file: File) -> std::io::Result<()> {
+    if file.metadata()?.is_file() {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+// Uncompressed '.fastq' output.
+pub struct FastqSink {
+    writer: BufWriter<File>,
+    bytes_written: u64,
+}
+
+impl FastqSink {
+    pub fn new(file: File) -> Self {
+        FastqSink {
+            writer: BufWriter::new(file),
+            bytes_written: 0,
         }
     }
 }
 
-pub fn create_writer(
-    path: PathBuf,
+impl RecordSink for FastqSink {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.bytes_written +=
+            write_fastq(&mut self.writer, record).map_err(|e| write_error(record, e))?;
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> Result<u64> {
+        self.writer.flush()?;
+        Ok(self.writer.get_ref().metadata()?.len())
+    }
+
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        sync_file(self.writer.into_inner().map_err(|e| e.into_error())?)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+// Compressed '.fastq.gz' output.
+pub struct GzipFastqSink {
+    writer: BufWriter<Box<dyn ZWriter<File>>>,
+    settings: GzipSettings,
+    bytes_written: u64,
+}
+
+impl GzipFastqSink {
+    pub fn new(file: File, settings: GzipSettings) -> Self {
+        GzipFastqSink {
+            writer: settings.writer(file),
+            settings,
+            bytes_written: 0,
+        }
+    }
+}
+
+impl RecordSink for GzipFastqSink {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.bytes_written +=
+            write_fastq(&mut self.writer, record).map_err(|e| write_error(record, e))?;
+        Ok(())
+    }
+
+    // Compressed outputs finish the current gzip member and continue with a new one, so the file
+    // up to the returned length is a complete gzip file.
+    fn checkpoint(&mut self) -> Result<u64> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut().finish()?;
+        let length = file.metadata()?.len();
+        self.writer = self.settings.writer(file);
+        Ok(length)
+    }
+
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        let (mut writer, buffer) = self.writer.into_parts();
+        let written = buffer
+            .map_err(|_| std::io::Error::other("buffer of a panicked writer"))
+            .and_then(|buffer| writer.write_all(&buffer));
+        // The compressor is finished in any case, as it reports the error of its writer thread.
+        let file = match (writer.finish(), written) {
+            (Ok(file), Ok(())) => file,
+            (Ok(_), Err(e)) => return Err(e),
+            (Err(e), _) => {
+                // A compressor that could not be finished panics when it is dropped.
+                std::mem::forget(writer);
+                return Err(match e {
+                    GzpError::Io(e) => e,
+                    e => std::io::Error::other(e),
+                });
+            }
+        };
+        sync_file(file)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+fn output_writer(
+    file: File,
     compress: &bool,
     num_threads: &usize,
     compression_level: &Option<u32>,
     pin_at: Option<usize>,
-) -> Result<OutputFile> {
-    let file = File::create(&path)
-        .map_err(|_e| anyhow!(RuntimeErrors::OutputNotWriteable(Some(path.clone()))))?;
+) -> Box<dyn RecordSink> {
     if *compress {
-        let writer = ZBuilder::<Gzip, _>::new()
-            .num_threads(*num_threads)
-            .compression_level(
-                compression_level
-                    .map_or_else(Default::default, |l| Compression::new((l).clamp(1, 9))),
-            )
-            .pin_threads(pin_at)
-            .from_writer(file);
-        Ok(OutputFile::Compressed(FastqWriter::from_bufwriter(
-            BufWriter::new(writer),
-        )))
+        let settings = GzipSettings {
+            num_threads: *num_threads,
+            compression_level: *compression_level,
+            pin_at,
+        };
+        Box::new(GzipFastqSink::new(file, settings))
     } else {
-        Ok(OutputFile::Plain(FastqWriter::new(file)))
+        Box::new(FastqSink::new(file))
+    }
+}
+
+pub fn create_writer(
+    path: PathBuf,
+    compress: &bool,
+    num_threads: &usize,
+    compression_level: &Option<u32>,
+    pin_at: Option<usize>,
+) -> Result<Box<dyn RecordSink>> {
+    let file = File::create(&path).map_err(|source| {
+        anyhow!(RuntimeErrors::OutputNotWriteable {
+            path: path.clone(),
+            source
+        })
+    })?;
+    Ok(output_writer(
+        file,
+        compress,
+        num_threads,
+        compression_level,
+        pin_at,
+    ))
+}
+
+// Opens an output of an interrupted run to continue writing after the last checkpoint.
+// Everything written after the checkpoint is discarded.
+pub fn resume_writer(
+    path: PathBuf,
+    length: u64,
+    compress: &bool,
+    num_threads: &usize,
+    compression_level: &Option<u32>,
+    pin_at: Option<usize>,
+) -> Result<Box<dyn RecordSink>> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .map_err(|source| {
+            anyhow!(RuntimeErrors::OutputNotWriteable {
+                path: path.clone(),
+                source
+            })
+        })?;
+    if file.metadata()?.is_file() {
+        file.set_len(length)?;
+    }
+    file.seek(SeekFrom::End(0))?;
+    Ok(output_writer(
+        file,
+        compress,
+        num_threads,
+        compression_level,
+        pin_at,
+    ))
+}
+
+// Outputs are written to temporary files next to their final paths and only renamed once all
+// records have been written. Unless the outputs are committed, the temporary files are removed
+// when the guard is dropped, e.g. because the processing failed.
+pub struct AtomicOutputs {
+    outputs: Vec<(PathBuf, Option<PathBuf>)>,
+    keep_partial: bool,
+    committed: bool,
+}
+
+impl AtomicOutputs {
+    pub fn new(paths: &[PathBuf], keep_partial: bool) -> Self {
+        let outputs = paths
+            .iter()
+            .map(|path| {
+                // FIFOs and devices like /dev/null can not be replaced, so they are written directly.
+                let is_special = fs::metadata(path).is_ok_and(|metadata| !metadata.is_file());
+                let temp = (!is_special).then(|| {
+                    let mut temp = path.as_os_str().to_owned();
+                    temp.push(".tmp");
+                    PathBuf::from(temp)
+                });
+                (path.clone(), temp)
+            })
+            .collect();
+        AtomicOutputs {
+            outputs,
+            keep_partial,
+            committed: false,
+        }
+    }
+
+    // Path that the output is written to while the processing is running.
+    pub fn write_path(&self, index: usize) -> &PathBuf {
+        let (path, temp) = &self.outputs[index];
+        temp.as_ref().unwrap_or(path)
+    }
+
+    // Moves all outputs to their final paths. The writers must have been closed before.
+    pub fn commit(mut self) -> Result<()> {
+        for (path, temp) in &self.outputs {
+            if let Some(temp) = temp {
+                fs::rename(temp, path).map_err(|source| {
+                    anyhow!(RuntimeErrors::OutputNotWriteable {
+                        path: path.clone(),
+                        source
+                    })
+                })?;
+            }
+        }
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicOutputs {
+    fn drop(&mut self) {
+        if self.committed || self.keep_partial {
+            return;
+        }
+        for temp in self.outputs.iter().filter_map(|(_, temp)| temp.as_ref()) {
+            // The processing has already failed, so a failed cleanup is not reported separately.
+            let _ = fs::remove_file(temp);
+        }
     }
 }
 
@@ -102,8 +474,20 @@ pub fn create_writer(
 //  OTHER UTILITIES
 ////////////////////////////////////////////////////////////////
 
+// What to do if an output file exists already.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    // Ask for confirmation. Without a terminal to ask on, the file is kept as with `Never`.
+    Prompt,
+    Never,
+    Always,
+    // Move the existing file to a backup next to it, see backup_path().
+    Backup,
+}
+
 // Checks whether an output path exists.
-pub fn check_outputpath(path: PathBuf, force: &bool) -> Result<PathBuf> {
+pub fn check_outputpath(path: PathBuf, overwrite: &OverwritePolicy) -> Result<PathBuf> {
     // Skip overwrite prompt for "/dev/null" -> can/will be used for singletons.
     if &path.to_string_lossy() == "/dev/null" {
         return Ok(path);
@@ -113,8 +497,8 @@ pub fn check_outputpath(path: PathBuf, force: &bool) -> Result<PathBuf> {
     fs::metadata() returns an Err() if the file does not exist (or there was an error accessing it).
     map_or() is used to convert the Err to an OK variant of path, because it is safe to write to that new path.
 
-    If fs::metadata(path) returns Ok(metadata), it will be inspected further: If it is a FIFO or the --force CLI flag
-    is active, also allow writing. Otherwise prompt and ask for confirmation.
+    If fs::metadata(path) returns Ok(metadata), it will be inspected further: FIFOs are always written to.
+    Otherwise, the overwrite policy decides.
     */
     fs::metadata(&path).map_or(Ok(path.clone()), |metadata| {
         // Since FIFOs are not supported on non-unix platforms, compilation would fail otherwise.
@@ -122,31 +506,141 @@ pub fn check_outputpath(path: PathBuf, force: &bool) -> Result<PathBuf> {
         {
             use std::os::unix::fs::FileTypeExt;
             // On unix platforms, we want to disable prompts for FIFOs for convenience reasons.
-            if metadata.file_type().is_fifo() || *force {
-                Ok(path)
-            } else {
-                prompt_overwrite(path)
+            if metadata.file_type().is_fifo() {
+                return Ok(path);
             }
         }
-        #[cfg(not(unix))]
-        {
-            if *force {
-                Ok(path) // Return Ok(path)
-            } else {
-                prompt_overwrite(path)
-            }
+        match overwrite {
+            OverwritePolicy::Always => Ok(path),
+            OverwritePolicy::Never => Err(anyhow!(RuntimeErrors::FileExists(path))),
+            // Devices can not be moved and are written to like FIFOs.
+            OverwritePolicy::Backup if !metadata.is_file() => Ok(path),
+            OverwritePolicy::Backup => backup_output(path),
+            OverwritePolicy::Prompt => prompt_overwrite(path),
         }
     })
 }
 
+// Identity of a file, which is compared to detect outputs that would replace an input or another
+// output, also through symlinks, hardlinks or different spellings of the same path.
+#[derive(Debug, PartialEq)]
+enum FileIdentity {
+    // Device and inode of an existing file.
+    Inode(u64, u64),
+    // Canonical path of an existing file on platforms without inodes, or of a file that does not
+    // exist yet.
+    Path(PathBuf),
+}
+
+#[cfg(unix)]
+fn existing_file_identity(_path: &Path, metadata: &fs::Metadata) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileIdentity::Inode(metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn existing_file_identity(path: &Path, _metadata: &fs::Metadata) -> Option<FileIdentity> {
+    fs::canonicalize(path).ok().map(FileIdentity::Path)
+}
+
+fn file_identity(path: &Path) -> Option<FileIdentity> {
+    match fs::metadata(path) {
+        // FIFOs and devices like /dev/null can be shared by several outputs.
+        Ok(metadata) if !metadata.is_file() => None,
+        Ok(metadata) => existing_file_identity(path, &metadata),
+        // The directory of a new file exists, unless the output can not be written anyway.
+        Err(_) => {
+            let name = path.file_name()?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+            Some(FileIdentity::Path(dir.join(name)))
+        }
+    }
+}
+
+// Checks that no output is the same file as an input or as another output. Otherwise, an input
+// would be replaced by its own output, e.g. with an input name without extension, to which no
+// suffix can be added.
+pub fn check_collisions(inputs: &[PathBuf], outputs: &[PathBuf]) -> Result<()> {
+    let inputs: Vec<(&PathBuf, FileIdentity)> = inputs
+        .iter()
+        .filter_map(|input| file_identity(input).map(|identity| (input, identity)))
+        .collect();
+    let mut previous: Vec<(&PathBuf, FileIdentity)> = Vec::new();
+    for output in outputs {
+        let Some(identity) = file_identity(output) else {
+            continue;
+        };
+        if let Some((input, _)) = inputs.iter().find(|(_, other)| *other == identity) {
+            return Err(anyhow!(RuntimeErrors::OutputIsInput(
+                output.clone(),
+                (*input).clone()
+            )));
+        }
+        if let Some((other, _)) = previous.iter().find(|(_, other)| *other == identity) {
+            return Err(anyhow!(RuntimeErrors::OutputCollision(
+                (*other).clone(),
+                output.clone()
+            )));
+        }
+        previous.push((output, identity));
+    }
+    Ok(())
+}
+
+// Returns the first of `<path>.bak`, `<path>.bak.1`, `<path>.bak.2`, ... that does not exist yet.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    let backup = PathBuf::from(&name);
+    if fs::symlink_metadata(&backup).is_err() {
+        return backup;
+    }
+    (1..)
+        .map(|number| {
+            let mut numbered = name.clone();
+            numbered.push(format!(".{}", number));
+            PathBuf::from(numbered)
+        })
+        .find(|numbered| fs::symlink_metadata(numbered).is_err())
+        .expect("a backup number is unused")
+}
+
+// Moves an existing output out of the way, so that it is kept after the new output is written.
+fn backup_output(path: PathBuf) -> Result<PathBuf> {
+    let backup = backup_path(&path);
+    fs::rename(&path, &backup).map_err(|source| {
+        anyhow!(RuntimeErrors::OutputNotWriteable {
+            path: backup.clone(),
+            source
+        })
+    })?;
+    println!(
+        "Existing {} was moved to {}",
+        path.display(),
+        backup.display()
+    );
+    Ok(path)
+}
+
 fn prompt_overwrite(path: PathBuf) -> Result<PathBuf> {
+    // Without a terminal, e.g. in a workflow manager, nobody can confirm and the file is kept.
+    // The prompt is not even shown then, as it could wait for input forever.
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Err(anyhow!(RuntimeErrors::FileExists(path)));
+    }
+    // A prompt that fails, e.g. because the terminal was closed, also keeps the file.
     if Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt(format!("{} exists. Overwrite?", path.display()))
-        .interact()?
+        .interact()
+        .unwrap_or(false)
     {
         Ok(path)
     } else {
-        Err(anyhow!(RuntimeErrors::FileExists(Some(path))))
+        Err(anyhow!(RuntimeErrors::FileExists(path)))
     }
 }
 
@@ -183,25 +677,145 @@ pub fn rectify_extension(mut path: PathBuf, compress: &bool) -> Result<PathBuf>
     Ok(path)
 }
 
-pub fn append_umi_to_path(path: &Path) -> PathBuf {
-    let path_str = path.as_os_str().to_string_lossy();
+// Joins several paths for messages, e.g. when multiple lanes are processed together.
+pub fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Suffix of output files that are named after the inputs, e.g. `read1_with_UMIs.fq`.
+pub const DEFAULT_SUFFIX: &str = "_with_UMIs";
 
-    let new_path_str = if path_str.contains('\\') || path_str.contains('/') {
-        // Path group: Match everything until a forward or backward slash not followed by a forward or backward slash non-greedy (*?)
-        // Stem group: Match literal dot zero or one time, and everything thereafter that is not a dot, yet followed by a literal dot.
-        // Extension group: Now match whatever is still left until the end $.
-        let re =
-            Regex::new(r"(?P<path>^.*(?:\\|/))[^/\\]*?(?P<stem>\.?[^\.]+)\.(?P<extension>.*)$")
-                .unwrap();
-        let new_path_str = re.replace(&path_str, "${path}${stem}_with_UMIs.${extension}");
-        new_path_str
-    } else {
-        // Simplified regex for the cases when the file name is given without any preceding path.
-        let re = Regex::new(r"(?P<stem>^\.?[^\.]+)\.(?P<extension>.*)$").unwrap();
-        let new_path_str = re.replace(&path_str, "${stem}_with_UMIs.${extension}");
-        new_path_str
+// Placeholders of --name-template.
+const NAME_PLACEHOLDERS: [&str; 3] = ["{sample}", "{read}", "{stem}"];
+
+// How output paths are derived from the inputs, unless they are given explicitly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputNaming {
+    outdir: Option<PathBuf>,
+    suffix: String,
+    template: Option<String>,
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        OutputNaming {
+            outdir: None,
+            suffix: DEFAULT_SUFFIX.to_string(),
+            template: None,
+        }
+    }
+}
+
+impl OutputNaming {
+    // Validates suffix and template, so that mistakes are reported before any file is written.
+    pub fn new(
+        outdir: Option<PathBuf>,
+        suffix: Option<&str>,
+        template: Option<&str>,
+    ) -> Result<Self> {
+        let is_file_name = |name: &str| !name.contains(['/', '\\']);
+        let suffix = suffix.unwrap_or(DEFAULT_SUFFIX);
+        if !is_file_name(suffix) {
+            return Err(anyhow!(RuntimeErrors::NameTemplateInvalid(
+                suffix.into(),
+                "the suffix must not contain a directory, please use --outdir instead".into()
+            )));
+        }
+        if let Some(template) = template {
+            let invalid = |reason: &str| {
+                anyhow!(RuntimeErrors::NameTemplateInvalid(
+                    template.into(),
+                    reason.into()
+                ))
+            };
+            let unknown = Regex::new(r"\{[^}]*\}")
+                .unwrap()
+                .find_iter(template)
+                .map(|placeholder| placeholder.as_str())
+                .find(|placeholder| !NAME_PLACEHOLDERS.contains(placeholder));
+            if let Some(placeholder) = unknown {
+                return Err(invalid(&format!(
+                    "unknown placeholder {placeholder}, please use {}",
+                    NAME_PLACEHOLDERS.join(", ")
+                )));
+            }
+            // Otherwise, both reads would be written to the same file.
+            if !template.contains("{read}") {
+                return Err(invalid("the template must contain {read}"));
+            }
+            if !is_file_name(template) {
+                return Err(invalid(
+                    "the template must not contain a directory, please use --outdir instead",
+                ));
+            }
+        }
+        Ok(OutputNaming {
+            outdir,
+            suffix: suffix.to_string(),
+            template: template.map(str::to_string),
+        })
+    }
+}
+
+// Name of a file up to the first dot, e.g. `read1` for `read1.fq.gz` or `.read1` for `.read1.fq`.
+fn file_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let (dot, name) = match name.strip_prefix('.') {
+        Some(name) => (".", name),
+        None => ("", name.as_ref()),
     };
-    PathBuf::from(new_path_str.to_string())
+    format!("{}{}", dot, name.split('.').next().unwrap_or_default())
+}
+
+// Inserts the suffix before the extension of the file name, e.g. `.test_with_UMIs.fastq.gz` for
+// `.test.fastq.gz`. Only the file name is matched, so that dots in directories are ignored. Names
+// without extension are returned unchanged, see check_collisions().
+fn append_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return path.to_path_buf();
+    };
+    // Stem group: Match literal dot zero or one time, and everything thereafter that is not a dot, yet followed by a literal dot.
+    // Extension group: Now match whatever is still left until the end $.
+    let re = Regex::new(r"^(?P<stem>\.?[^\.]+)\.(?P<extension>.*)$").unwrap();
+    let new_name = re.replace(&name, |caps: &Captures| {
+        format!("{}{}.{}", &caps["stem"], suffix, &caps["extension"])
+    });
+    path.with_file_name(new_name.as_ref())
+}
+
+// Derives the output path of a read from its input. By default, the suffix is inserted before the
+// extension of the input. A name template replaces the file name, with the sample name or, if
+// there is none, the stem of the input for {sample}. The output is written to the output
+// directory if one was given, otherwise next to the input.
+pub fn append_umi_to_path(
+    path: &Path,
+    naming: &OutputNaming,
+    read: &str,
+    sample: Option<&str>,
+) -> PathBuf {
+    let output = match &naming.template {
+        Some(template) => {
+            let stem = file_stem(path);
+            path.with_file_name(
+                template
+                    .replace("{sample}", sample.unwrap_or(&stem))
+                    .replace("{read}", read)
+                    .replace("{stem}", &stem),
+            )
+        }
+        None => append_suffix(path, &naming.suffix),
+    };
+    match (&naming.outdir, output.file_name()) {
+        (Some(outdir), Some(name)) => outdir.join(name),
+        _ => output,
+    }
 }
 
 #[cfg(test)]
@@ -217,26 +831,246 @@ mod tests {
         (temp_dir, mock_file)
     }
 
+    #[test]
+    fn test_read_fastqs_concatenates_files() {
+        let read1 = PathBuf::from("tests/seqdata/read1.fq");
+        let read1_gz = PathBuf::from("tests/seqdata/read1.fq.gz");
+
+        let single = read_fastqs(std::slice::from_ref(&read1), false)
+            .unwrap()
+            .count();
+        let records: Vec<Record> = read_fastqs(&[read1, read1_gz], false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2 * single);
+        assert_eq!(records[0], records[single]);
+    }
+
+    #[test]
+    fn test_read_fastqs_fails_on_missing_file() {
+        let read1 = PathBuf::from("tests/seqdata/read1.fq");
+        let missing = PathBuf::from("tests/seqdata/missing.fq");
+
+        let result = read_fastqs(&[read1, missing], false);
+        assert!(result.is_err());
+        assert!(format!("{:#}", result.err().unwrap()).contains("missing.fq does not exist"));
+    }
+
+    #[test]
+    fn test_check_record() {
+        let check = |id: &str, seq: &[u8], qual: &[u8]| {
+            check_record(&Record::with_attrs(id, None, seq, qual))
+        };
+        assert_eq!(check("READ", b"ACGTNryk", b"!#FI~~~~"), Ok(()));
+        assert_eq!(
+            check("", b"ACGT", b"FFFF"),
+            Err("the read name is empty".to_string())
+        );
+        assert_eq!(
+            check("READ", b"ACGT", b"FFF"),
+            Err("4 bases, but 3 quality scores".to_string())
+        );
+        assert_eq!(
+            check("READ", b"AC.T", b"FFFF"),
+            Err("'.' is not a nucleotide code".to_string())
+        );
+        assert_eq!(
+            check("READ", b"ACGT", b"FF F"),
+            Err("' ' is not a quality character".to_string())
+        );
+    }
+
+    #[test]
+    fn test_read_fastqs_strict() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let path = temp_dir.path().join("reads.fq");
+        fs::write(&path, "@READ1\nACGT\n+\nFFFF\n@READ2\nACGT\n+\nFFF\n").unwrap();
+
+        // The parser accepts the record with a missing quality score, only the strict check fails.
+        assert_eq!(
+            read_fastqs(std::slice::from_ref(&path), false)
+                .unwrap()
+                .count(),
+            2
+        );
+        let mut records = read_fastqs(std::slice::from_ref(&path), true).unwrap();
+        assert!(records.next().unwrap().is_ok());
+        match records
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .downcast::<RuntimeErrors>()
+        {
+            Ok(RuntimeErrors::MalformedRecord {
+                path: file,
+                record,
+                reason,
+            }) => {
+                assert_eq!(file, path);
+                assert_eq!(record, 2);
+                assert_eq!(reason, "4 bases, but 3 quality scores");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_checkpoint_finishes_gzip_member() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let output = temp_dir.path().join("output.fq.gz");
+        let records: Vec<Record> = read_fastqs(&[PathBuf::from("tests/seqdata/read1.fq")], false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let mut writer = create_writer(output.clone(), &true, &2, &None, None).unwrap();
+        writer.write_record(&records[0]).unwrap();
+        let length = writer.checkpoint().unwrap();
+        writer.write_record(&records[1]).unwrap();
+        drop(writer);
+        assert!(fs::metadata(&output).unwrap().len() > length);
+
+        // Both members are read as one file.
+        let written: Vec<Record> = read_fastqs(std::slice::from_ref(&output), false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(written, records[..2]);
+
+        // Resuming discards everything after the checkpoint.
+        let mut writer = resume_writer(output.clone(), length, &true, &2, &None, None).unwrap();
+        writer.write_record(&records[2]).unwrap();
+        writer.finish().unwrap();
+        let written: Vec<Record> = read_fastqs(std::slice::from_ref(&output), false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(written, vec![records[0].clone(), records[2].clone()]);
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_finish_reports_full_disk() {
+        let records: Vec<Record> = read_fastqs(&[PathBuf::from("tests/seqdata/read1.fq")], false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // Writes to /dev/full fail with "No space left on device".
+        let mut writer =
+            create_writer(PathBuf::from("/dev/full"), &false, &2, &None, None).unwrap();
+        writer.write_record(&records[0]).unwrap();
+        let error = writer.finish().unwrap_err();
+        assert!(error.to_string().contains("No space left on device"));
+
+        // Depending on the timing, the compressor only reports that its writer thread has stopped.
+        let mut writer = create_writer(PathBuf::from("/dev/full"), &true, &2, &None, None).unwrap();
+        writer.write_record(&records[0]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_sink_writes_pairs() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let records: Vec<Record> = read_fastqs(&[PathBuf::from("tests/seqdata/read1.fq")], false)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        for output in ["pairs.fq", "pairs.fq.gz"] {
+            let path = temp_dir.path().join(output);
+            let mut sink =
+                create_writer(path.clone(), &output.ends_with(".gz"), &1, &None, None).unwrap();
+            sink.write_pair(&records[0], &records[1]).unwrap();
+            let bytes_written = sink.bytes_written();
+            sink.finish().unwrap();
+
+            // The pair is written as interleaved records.
+            let written: Vec<Record> = read_fastqs(&[path], false)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(written, records[..2]);
+            assert_eq!(
+                bytes_written,
+                fs::read_to_string("tests/seqdata/read1.fq")
+                    .unwrap()
+                    .lines()
+                    .take(8)
+                    .map(|line| line.len() as u64 + 1)
+                    .sum::<u64>()
+            );
+        }
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_atomic_outputs() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let output = temp_dir.path().join("output.fq");
+        let paths = vec![output.clone(), PathBuf::from("/dev/null")];
+
+        // Committed outputs are moved to their final paths.
+        let outputs = AtomicOutputs::new(&paths, false);
+        assert_eq!(
+            outputs.write_path(0),
+            &temp_dir.path().join("output.fq.tmp")
+        );
+        assert_eq!(outputs.write_path(1), &PathBuf::from("/dev/null"));
+        fs::write(outputs.write_path(0), "@read").unwrap();
+        outputs.commit().unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "@read");
+        assert!(!temp_dir.path().join("output.fq.tmp").exists());
+
+        // Outputs that are not committed are removed, unless they should be kept.
+        let outputs = AtomicOutputs::new(&paths, false);
+        fs::write(outputs.write_path(0), "@partial").unwrap();
+        drop(outputs);
+        assert!(!temp_dir.path().join("output.fq.tmp").exists());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "@read");
+
+        let outputs = AtomicOutputs::new(&paths, true);
+        fs::write(outputs.write_path(0), "@partial").unwrap();
+        drop(outputs);
+        assert!(temp_dir.path().join("output.fq.tmp").exists());
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
     #[test]
     fn test_correctly_derive_output_name() {
         // plain file with simple extension
         let p = PathBuf::from("test.fastq");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(result, PathBuf::from("test_with_UMIs.fastq"));
 
         // plain file with multiple extensions
         let p = PathBuf::from("test.fastq.gz");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(result, PathBuf::from("test_with_UMIs.fastq.gz"));
 
         // path and file with multiple extensions
         let p = PathBuf::from("/some/path/test.fastq.gz");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(result, PathBuf::from("/some/path/test_with_UMIs.fastq.gz"));
 
         // path with hidden dir and file with multiple extensions
         let p = PathBuf::from("/some/.hidden/path/test.fastq.gz");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(
             result,
             PathBuf::from("/some/.hidden/path/test_with_UMIs.fastq.gz")
@@ -244,7 +1078,7 @@ mod tests {
 
         // path with hidden dir and hidden file with multiple extensions
         let p = PathBuf::from("/some/.hidden/path/.test.fastq.gz");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(
             result,
             PathBuf::from("/some/.hidden/path/.test_with_UMIs.fastq.gz")
@@ -252,11 +1086,103 @@ mod tests {
 
         // relative path with hidden dir and hidden file with multiple extensions
         let p = PathBuf::from("./some/.hidden/path/.test.fastq.gz");
-        let result = append_umi_to_path(&p);
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
         assert_eq!(
             result,
             PathBuf::from("./some/.hidden/path/.test_with_UMIs.fastq.gz")
         );
+
+        // file without extension in a hidden dir keeps its name
+        let p = PathBuf::from("/some/.hidden/reads");
+        let result = append_umi_to_path(&p, &OutputNaming::default(), "R1", None);
+        assert_eq!(result, PathBuf::from("/some/.hidden/reads"));
+    }
+
+    #[test]
+    fn test_derive_output_name_with_suffix_and_outdir() {
+        let naming = OutputNaming::new(None, Some(".umi"), None).unwrap();
+        let derive = |path: &str| append_umi_to_path(Path::new(path), &naming, "R1", None);
+        assert_eq!(derive("test.fastq.gz"), PathBuf::from("test.umi.fastq.gz"));
+        assert_eq!(
+            derive("/some/.hidden/path/.test.fastq.gz"),
+            PathBuf::from("/some/.hidden/path/.test.umi.fastq.gz")
+        );
+
+        // The output directory replaces the directory of the input, even if it contains dots.
+        let naming = OutputNaming::new(Some(PathBuf::from("/scratch/run.v2")), None, None).unwrap();
+        let derive = |path: &str| append_umi_to_path(Path::new(path), &naming, "R1", None);
+        assert_eq!(
+            derive("/some/path/test.fastq.gz"),
+            PathBuf::from("/scratch/run.v2/test_with_UMIs.fastq.gz")
+        );
+        assert_eq!(
+            derive("./some/.hidden/path/.test.R1.fastq"),
+            PathBuf::from("/scratch/run.v2/.test_with_UMIs.R1.fastq")
+        );
+        assert_eq!(
+            derive("test.fq"),
+            PathBuf::from("/scratch/run.v2/test_with_UMIs.fq")
+        );
+    }
+
+    #[test]
+    fn test_derive_output_name_from_template() {
+        let naming = OutputNaming::new(None, None, Some("{sample}_{read}.umi.fastq.gz")).unwrap();
+        assert_eq!(
+            append_umi_to_path(
+                Path::new("/data/lane1.R1.fq"),
+                &naming,
+                "R1",
+                Some("P1_1001")
+            ),
+            PathBuf::from("/data/P1_1001_R1.umi.fastq.gz")
+        );
+        // Without a sample name, the stem of the input is used. Hidden files keep their dot.
+        assert_eq!(
+            append_umi_to_path(Path::new("/data/.lane1.R2.fq"), &naming, "R2", None),
+            PathBuf::from("/data/.lane1_R2.umi.fastq.gz")
+        );
+
+        // The extension is rectified afterwards like for all other outputs.
+        let naming = OutputNaming::new(
+            Some(PathBuf::from("out.d")),
+            None,
+            Some("{stem}.{read}.fastq.gz"),
+        )
+        .unwrap();
+        let output = append_umi_to_path(Path::new("reads.fq"), &naming, "R2", None);
+        assert_eq!(output, PathBuf::from("out.d/reads.R2.fastq.gz"));
+        assert_eq!(
+            rectify_extension(output, &false).unwrap(),
+            PathBuf::from("out.d/reads.R2.fastq")
+        );
+        let output = append_umi_to_path(Path::new("reads.fq"), &naming, "R1", None);
+        assert_eq!(
+            rectify_extension(output.with_file_name("reads.R1"), &true).unwrap(),
+            PathBuf::from("out.d/reads.R1.gz")
+        );
+    }
+
+    #[test]
+    fn test_invalid_output_naming() {
+        for (suffix, template, reason) in [
+            (
+                None,
+                Some("{sample}_{lane}.fq"),
+                "unknown placeholder {lane}",
+            ),
+            (None, Some("{sample}.fq"), "must contain {read}"),
+            (
+                None,
+                Some("out/{sample}_{read}.fq"),
+                "must not contain a directory",
+            ),
+            (Some("/umi"), None, "must not contain a directory"),
+        ] {
+            let result = OutputNaming::new(None, suffix, template);
+            let message = result.unwrap_err().to_string();
+            assert!(message.contains(reason), "{}", message);
+        }
     }
 
     #[test]
@@ -290,9 +1216,8 @@ mod tests {
     #[test]
     fn test_check_outputpath_existing_file_with_force() {
         let (temp_dir, file_path) = create_mock_file();
-        let force = true;
 
-        let result = check_outputpath(file_path.path().to_path_buf(), &force);
+        let result = check_outputpath(file_path.path().to_path_buf(), &OverwritePolicy::Always);
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), file_path.path().to_path_buf());
@@ -306,9 +1231,8 @@ mod tests {
     fn test_check_outputpath_new_file() {
         let (temp_dir, _file_path) = create_mock_file();
         let file_path = temp_dir.path().join("new_file");
-        let force = true;
 
-        let result = check_outputpath(file_path, &force);
+        let result = check_outputpath(file_path, &OverwritePolicy::Never);
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), temp_dir.path().join("new_file"));
@@ -317,4 +1241,108 @@ mod tests {
             .close()
             .expect("Failed to remove temporary directory");
     }
+
+    #[test]
+    fn test_check_outputpath_existing_file_never() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let file_path = temp_dir.path().join("existing.fq");
+        fs::write(&file_path, "existing").unwrap();
+
+        // Tests run without a terminal, so the prompt falls back to never overwriting.
+        for policy in [OverwritePolicy::Never, OverwritePolicy::Prompt] {
+            let result = check_outputpath(file_path.clone(), &policy);
+            assert!(matches!(
+                result.unwrap_err().downcast_ref::<RuntimeErrors>(),
+                Some(RuntimeErrors::FileExists(_))
+            ));
+        }
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "existing");
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_check_outputpath_backup() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let file_path = temp_dir.path().join("existing.fq");
+
+        // Every run keeps the previous file in a new backup.
+        for content in ["first", "second", "third"] {
+            fs::write(&file_path, content).unwrap();
+            let result = check_outputpath(file_path.clone(), &OverwritePolicy::Backup);
+            assert_eq!(result.unwrap(), file_path);
+            assert!(!file_path.exists());
+        }
+        let backup = |name: &str| fs::read_to_string(temp_dir.path().join(name)).unwrap();
+        assert_eq!(backup("existing.fq.bak"), "first");
+        assert_eq!(backup("existing.fq.bak.1"), "second");
+        assert_eq!(backup("existing.fq.bak.2"), "third");
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_check_collisions() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let input = temp_dir.path().join("reads");
+        fs::write(&input, "input").unwrap();
+        let inputs = vec![input.clone()];
+        let collision = |outputs: &[PathBuf]| {
+            check_collisions(&inputs, outputs)
+                .err()
+                .and_then(|error| error.downcast::<RuntimeErrors>().ok())
+        };
+
+        // Input without extension, from which the same name is derived.
+        let output = append_umi_to_path(&input, &OutputNaming::default(), "R1", None);
+        assert!(matches!(
+            collision(&[output]),
+            Some(RuntimeErrors::OutputIsInput(..))
+        ));
+
+        // Other spellings of the same path, a symlink and a hardlink.
+        let relative = temp_dir.path().join("sub/../reads");
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&input, temp_dir.path().join("symlink.fq")).unwrap();
+        fs::hard_link(&input, temp_dir.path().join("hardlink.fq")).unwrap();
+        let mut aliases = vec![relative, temp_dir.path().join("hardlink.fq")];
+        if cfg!(unix) {
+            aliases.push(temp_dir.path().join("symlink.fq"));
+        }
+        for alias in aliases {
+            assert!(matches!(
+                collision(&[temp_dir.path().join("out.fq"), alias]),
+                Some(RuntimeErrors::OutputIsInput(..))
+            ));
+        }
+
+        // Two outputs that do not exist yet, but point to the same file.
+        assert!(matches!(
+            collision(&[
+                temp_dir.path().join("out.fq"),
+                temp_dir.path().join("sub/../out.fq")
+            ]),
+            Some(RuntimeErrors::OutputCollision(..))
+        ));
+
+        // Distinct outputs and special files, which may be shared, are fine.
+        assert!(collision(&[
+            temp_dir.path().join("out1.fq"),
+            temp_dir.path().join("out2.fq")
+        ])
+        .is_none());
+        if cfg!(unix) {
+            let null = PathBuf::from("/dev/null");
+            assert!(collision(&[null.clone(), null]).is_none());
+        }
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
 }
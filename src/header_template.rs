@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+
+use crate::read_header::casava_index;
+use crate::umi_errors::RuntimeErrors;
+
+// Layout of the output header that matches the behaviour of umi-transfer before templates were introduced.
+pub const DEFAULT_TEMPLATE: &str = "{id}{delim}{umi} {desc}";
+
+// Values that can be inserted into the output header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placeholder {
+    Id,
+    Delim,
+    Umi,
+    UmiQual,
+    Desc,
+    ReadNr,
+    Sample,
+    Index,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(Self::Id),
+            "delim" => Some(Self::Delim),
+            "umi" => Some(Self::Umi),
+            "umi_qual" => Some(Self::UmiQual),
+            "desc" => Some(Self::Desc),
+            "read_nr" => Some(Self::ReadNr),
+            "sample" => Some(Self::Sample),
+            "index" => Some(Self::Index),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Value(Placeholder),
+}
+
+// Parsed --header-template. The delimiter and the sample name are the same for all records,
+// so they are fixed when the template is created.
+#[derive(Debug, Clone)]
+pub struct HeaderTemplate {
+    segments: Vec<Segment>,
+    delim: String,
+    sample: Option<String>,
+    umi_separator: Option<String>,
+}
+
+// Finds the text of `{id}<separator>{umi}` at the end of the read name, e.g. `{delim}` in the default template.
+fn umi_separator(segments: &[Segment], delim: &str) -> Option<String> {
+    segments.windows(3).enumerate().find_map(|(pos, window)| {
+        let separator = match window {
+            [Segment::Value(Placeholder::Id), Segment::Value(Placeholder::Delim), Segment::Value(Placeholder::Umi)] => {
+                delim
+            }
+            [Segment::Value(Placeholder::Id), Segment::Text(text), Segment::Value(Placeholder::Umi)] => {
+                text
+            }
+            _ => return None,
+        };
+        let ends_name = match segments.get(pos + 3) {
+            None => true,
+            Some(Segment::Text(text)) => text.starts_with(char::is_whitespace),
+            Some(Segment::Value(_)) => false,
+        };
+        (ends_name && !separator.is_empty() && !separator.contains(char::is_whitespace))
+            .then(|| separator.to_string())
+    })
+}
+
+impl HeaderTemplate {
+    // Parses and validates a template, so that mistakes are reported before any file is written.
+    pub fn new(template: &str, delim: &str, sample: Option<&str>) -> Result<Self> {
+        let invalid = |reason: &str| {
+            anyhow!(RuntimeErrors::HeaderTemplateInvalid(
+                template.into(),
+                reason.into()
+            ))
+        };
+
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(c) = rest.chars().next() {
+            // Literal braces are written as {{ and }}.
+            if let Some(remainder) = rest.strip_prefix("{{") {
+                text.push('{');
+                rest = remainder;
+            } else if let Some(remainder) = rest.strip_prefix("}}") {
+                text.push('}');
+                rest = remainder;
+            } else if let Some(remainder) = rest.strip_prefix('{') {
+                let (name, remainder) = remainder
+                    .split_once('}')
+                    .ok_or_else(|| invalid("unclosed {"))?;
+                let placeholder = Placeholder::parse(name)
+                    .ok_or_else(|| invalid(&format!("unknown placeholder {{{}}}", name)))?;
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Value(placeholder));
+                rest = remainder;
+            } else if c == '}' {
+                return Err(invalid("unmatched }"));
+            } else {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        if template.starts_with(char::is_whitespace) || segments.is_empty() {
+            return Err(invalid("the read name must not be empty"));
+        }
+        if sample.is_none() && segments.contains(&Segment::Value(Placeholder::Sample)) {
+            return Err(invalid("{sample} requires a sample name"));
+        }
+
+        let umi_separator = umi_separator(&segments, delim);
+        Ok(HeaderTemplate {
+            segments,
+            delim: delim.to_string(),
+            sample: sample.map(str::to_string),
+            umi_separator,
+        })
+    }
+
+    // Text between the read name and the UMI, if the template appends the UMI to the read name.
+    // Only then can a UMI added by an earlier run be recognized at the end of a read name.
+    pub fn umi_separator(&self) -> Option<&str> {
+        self.umi_separator.as_deref()
+    }
+
+    // Builds the header of a record. The text up to the first whitespace becomes the read name,
+    // the remainder the description.
+    pub fn render(
+        &self,
+        id: &str,
+        desc: Option<&str>,
+        umi: &[u8],
+        umi_qual: &[u8],
+        read_nr: u8,
+    ) -> Result<(String, Option<String>)> {
+        let mut header = String::with_capacity(id.len() + umi.len() + 64);
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => header.push_str(text),
+                Segment::Value(Placeholder::Id) => header.push_str(id),
+                Segment::Value(Placeholder::Delim) => header.push_str(&self.delim),
+                Segment::Value(Placeholder::Umi) => header.push_str(std::str::from_utf8(umi)?),
+                Segment::Value(Placeholder::UmiQual) => {
+                    header.push_str(std::str::from_utf8(umi_qual)?)
+                }
+                Segment::Value(Placeholder::Desc) => header.push_str(desc.unwrap_or_default()),
+                Segment::Value(Placeholder::ReadNr) => header.push_str(&read_nr.to_string()),
+                Segment::Value(Placeholder::Sample) => {
+                    header.push_str(self.sample.as_deref().unwrap_or_default())
+                }
+                Segment::Value(Placeholder::Index) => {
+                    header.push_str(casava_index(desc).unwrap_or_default())
+                }
+            }
+        }
+
+        let header = header.trim_end();
+        match header.split_once(char::is_whitespace) {
+            Some((id, desc)) => Ok((id.to_string(), Some(desc.trim_start().to_string()))),
+            None => Ok((header.to_string(), None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const ID: &str = "SCILIFELAB:500:NGISTLM:1:1101:2446:1031";
+    const DESC: Option<&str> = Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT");
+
+    #[test]
+    fn test_default_template() {
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, ":", None).unwrap();
+        let (id, desc) = template
+            .render(ID, DESC, b"ACCAGCTA", b"FFFFFFFF", 1)
+            .unwrap();
+        assert_eq!(id, format!("{}:ACCAGCTA", ID));
+        assert_eq!(desc.as_deref(), DESC);
+
+        // Without description, no trailing whitespace is written.
+        let (id, desc) = template
+            .render(ID, None, b"ACCAGCTA", b"FFFFFFFF", 1)
+            .unwrap();
+        assert_eq!(id, format!("{}:ACCAGCTA", ID));
+        assert_eq!(desc, None);
+    }
+
+    #[test]
+    fn test_template_with_all_placeholders() {
+        let template = HeaderTemplate::new(
+            "{id}_{umi} RX:Z:{umi} QX:Z:{umi_qual} RN:{read_nr} SM:{sample} BC:{index} {{{desc}}}",
+            ":",
+            Some("P123_1001"),
+        )
+        .unwrap();
+        let (id, desc) = template
+            .render(ID, DESC, b"ACCAGCTA", b"FFFF#FFF", 2)
+            .unwrap();
+        assert_eq!(id, format!("{}_ACCAGCTA", ID));
+        assert_eq!(
+            desc.as_deref(),
+            Some("RX:Z:ACCAGCTA QX:Z:FFFF#FFF RN:2 SM:P123_1001 BC:GCTTCAGGGT+AAGGTAGCGT {1:N:0:GCTTCAGGGT+AAGGTAGCGT}")
+        );
+    }
+
+    #[test]
+    fn test_umi_separator() {
+        for (template, separator) in [
+            (DEFAULT_TEMPLATE, Some(":")),
+            ("{id}_{umi}", Some("_")),
+            ("{id}_{umi} RX:Z:{umi}", Some("_")),
+            ("{id} RX:Z:{umi}", None),
+            ("{id}{umi}", None),
+            ("{id}{delim}{umi}_{read_nr}", None),
+            ("{sample}_{id}", None),
+        ] {
+            let template = HeaderTemplate::new(template, ":", Some("P123_1001")).unwrap();
+            assert_eq!(template.umi_separator(), separator);
+        }
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for (template, reason) in [
+            ("{id}{delim}{umis}", "unknown placeholder {umis}"),
+            ("{id}{delim}{umi", "unclosed {"),
+            ("{id}}", "unmatched }"),
+            (" {id}", "the read name must not be empty"),
+            ("", "the read name must not be empty"),
+            ("{id}_{sample}", "{sample} requires a sample name"),
+        ] {
+            let result = HeaderTemplate::new(template, ":", None);
+            let message = result.unwrap_err().to_string();
+            assert!(message.contains(reason), "{}: {}", template, message);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::umi_batch::Sample;
+use umi_transfer::umi_errors::RuntimeErrors;
+
+// File name of a FastQ file as written by bcl2fastq and BCL Convert, e.g. Sample_S1_L001_R1_001.fastq.gz.
+// The lane is missing if lane splitting was turned off.
+const ILLUMINA_NAME: &str = r"^(?P<sample>.+)_S(?P<number>\d+)(?:_L(?P<lane>\d{3}))?_(?P<read>[RI][1-4])_001\.f(?:ast)?q(?:\.gz)?$";
+
+// Reads of unassigned clusters, which are not processed unless explicitly requested.
+const UNDETERMINED: &str = "Undetermined";
+
+// Parses the read (R1, R2, I1...) argument of --umi-read.
+pub fn parse_read(read: &str) -> Result<String, String> {
+    let read = read.to_uppercase();
+    if Regex::new(r"^[RI][1-4]$").unwrap().is_match(&read) {
+        Ok(read)
+    } else {
+        Err(format!(
+            "{read} is not a valid read. Please choose one of R1-R4 or I1-I4."
+        ))
+    }
+}
+
+// Key to group the files of a sample and lane.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ReadGroup {
+    dir: PathBuf,
+    sample: String,
+    number: String,
+    lane: Option<String>,
+}
+
+impl ReadGroup {
+    fn name(&self) -> String {
+        match &self.lane {
+            Some(lane) => format!("{}_S{}_L{}", self.sample, self.number, lane),
+            None => format!("{}_S{}", self.sample, self.number),
+        }
+    }
+}
+
+// Symlinked directories are followed, but every directory is searched only once, so that a link to
+// a parent directory does not lead into a cycle. The entries are sorted, so that a directory that
+// can be reached on several paths is always found on the same one.
+fn collect_fastqs(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let not_found = |source| {
+        anyhow!(RuntimeErrors::FileNotFound {
+            path: dir.into(),
+            source
+        })
+    };
+    if !visited.insert(fs::canonicalize(dir).map_err(not_found)?) {
+        return Ok(());
+    }
+    let mut paths = fs::read_dir(dir)
+        .map_err(not_found)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_fastqs(&path, files, visited)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Searches a directory recursively for FastQ files with Illumina names and groups them by sample and lane.
+// From every group, the UMI read is taken as UMI input and the two remaining sequencing reads as read inputs.
+// If lanes are merged, the files of all lanes are grouped and the outputs are named after the sample.
+// Groups that lack files are returned as errors, so that they fail like samples that can not be processed.
+pub fn discover_samples(
+    dir: &Path,
+    umi_read: &str,
+    merge_lanes: bool,
+    undetermined: bool,
+) -> Result<Vec<(String, Result<Sample>)>> {
+    let re = Regex::new(ILLUMINA_NAME).unwrap();
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_fastqs(dir, &mut files, &mut HashSet::new())?;
+    // Sorting the paths also sorts the lanes of a sample in ascending order.
+    files.sort();
+
+    let mut groups: BTreeMap<ReadGroup, BTreeMap<String, Vec<PathBuf>>> = BTreeMap::new();
+    for path in files {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Some(caps) = re.captures(&file_name) else {
+            continue;
+        };
+        if !undetermined && &caps["sample"] == UNDETERMINED {
+            continue;
+        }
+        let group = ReadGroup {
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            sample: caps["sample"].to_string(),
+            number: caps["number"].to_string(),
+            lane: caps
+                .name("lane")
+                .filter(|_| !merge_lanes)
+                .map(|lane| lane.as_str().to_string()),
+        };
+        groups
+            .entry(group)
+            .or_default()
+            .entry(caps["read"].to_string())
+            .or_default()
+            .push(path);
+    }
+
+    if groups.is_empty() {
+        return Err(anyhow!(RuntimeErrors::NoSamplesFound(dir.into())));
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(group, reads)| {
+            (
+                group.name(),
+                group_sample(group, reads, umi_read, merge_lanes),
+            )
+        })
+        .collect())
+}
+
+// Takes the inputs of a sample from the files of a read group, which are grouped by read.
+fn group_sample(
+    group: ReadGroup,
+    mut reads: BTreeMap<String, Vec<PathBuf>>,
+    umi_read: &str,
+    merge_lanes: bool,
+) -> Result<Sample> {
+    let ru_in = reads.remove(umi_read).ok_or_else(|| {
+        anyhow!(RuntimeErrors::IncompleteReadGroup(
+            group.name(),
+            format!("no {umi_read} file to take the UMIs from")
+        ))
+    })?;
+    // BTreeMap keys are sorted, so the sequencing reads are taken in ascending order.
+    let mut sequencing_reads = reads.into_iter().filter(|(read, _)| read.starts_with('R'));
+    match (
+        sequencing_reads.next(),
+        sequencing_reads.next(),
+        sequencing_reads.next(),
+    ) {
+        (Some((_, r1_in)), Some((_, r2_in)), None)
+            if r1_in.len() == ru_in.len() && r2_in.len() == ru_in.len() =>
+        {
+            Ok(Sample {
+                name: group.name(),
+                out_prefix: merge_lanes.then(|| group.dir.join(group.name())),
+                r1_in,
+                r2_in,
+                ru_in,
+            })
+        }
+        (Some(_), Some(_), None) => Err(anyhow!(RuntimeErrors::IncompleteReadGroup(
+            group.name(),
+            "the reads were not found for the same number of lanes".to_string()
+        ))),
+        _ => Err(anyhow!(RuntimeErrors::IncompleteReadGroup(
+            group.name(),
+            format!("expected exactly two read files besides {umi_read}")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+
+    fn create_run_dir(files: &[&str]) -> TempDir {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        for file in files {
+            temp_dir.child(file).touch().unwrap();
+        }
+        temp_dir
+    }
+
+    // Samples of read groups that are expected to be complete.
+    fn complete_samples(
+        dir: &Path,
+        umi_read: &str,
+        merge_lanes: bool,
+        undetermined: bool,
+    ) -> Vec<Sample> {
+        discover_samples(dir, umi_read, merge_lanes, undetermined)
+            .unwrap()
+            .into_iter()
+            .map(|(_, sample)| sample.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_read() {
+        assert_eq!(parse_read("r2"), Ok("R2".to_string()));
+        assert_eq!(parse_read("I1"), Ok("I1".to_string()));
+        assert!(parse_read("R5").is_err());
+        assert!(parse_read("UMI").is_err());
+    }
+
+    #[test]
+    fn test_discover_samples_groups_by_sample_and_lane() {
+        let temp_dir = create_run_dir(&[
+            "P1_1001_S1_L001_R1_001.fastq.gz",
+            "P1_1001_S1_L001_R2_001.fastq.gz",
+            "P1_1001_S1_L001_R3_001.fastq.gz",
+            "P1_1001_S1_L001_I1_001.fastq.gz",
+            "P1_1001_S1_L002_R1_001.fastq.gz",
+            "P1_1001_S1_L002_R2_001.fastq.gz",
+            "P1_1001_S1_L002_R3_001.fastq.gz",
+            "project/P1_1002_S2_R1_001.fq",
+            "project/P1_1002_S2_R2_001.fq",
+            "project/P1_1002_S2_R3_001.fq",
+            "Undetermined_S0_L001_R1_001.fastq.gz",
+            "Reports/summary.html",
+        ]);
+
+        let samples = complete_samples(temp_dir.path(), "R2", false, false);
+        assert_eq!(samples.len(), 3);
+
+        assert_eq!(samples[0].name, "P1_1001_S1_L001");
+        assert_eq!(
+            samples[0].r1_in,
+            vec![temp_dir.path().join("P1_1001_S1_L001_R1_001.fastq.gz")]
+        );
+        assert_eq!(
+            samples[0].r2_in,
+            vec![temp_dir.path().join("P1_1001_S1_L001_R3_001.fastq.gz")]
+        );
+        assert_eq!(
+            samples[0].ru_in,
+            vec![temp_dir.path().join("P1_1001_S1_L001_R2_001.fastq.gz")]
+        );
+        assert_eq!(samples[0].out_prefix, None);
+        assert_eq!(samples[1].name, "P1_1001_S1_L002");
+        assert_eq!(samples[2].name, "P1_1002_S2");
+        assert_eq!(
+            samples[2].ru_in,
+            vec![temp_dir.path().join("project/P1_1002_S2_R2_001.fq")]
+        );
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_discover_samples_merges_lanes() {
+        let temp_dir = create_run_dir(&[
+            "P1_1001_S1_L002_R1_001.fastq.gz",
+            "P1_1001_S1_L002_R2_001.fastq.gz",
+            "P1_1001_S1_L002_R3_001.fastq.gz",
+            "P1_1001_S1_L001_R1_001.fastq.gz",
+            "P1_1001_S1_L001_R2_001.fastq.gz",
+            "P1_1001_S1_L001_R3_001.fastq.gz",
+        ]);
+
+        let samples = complete_samples(temp_dir.path(), "R2", true, false);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].name, "P1_1001_S1");
+        assert_eq!(
+            samples[0].ru_in,
+            vec![
+                temp_dir.path().join("P1_1001_S1_L001_R2_001.fastq.gz"),
+                temp_dir.path().join("P1_1001_S1_L002_R2_001.fastq.gz")
+            ]
+        );
+        assert_eq!(
+            samples[0].out_prefix,
+            Some(temp_dir.path().join("P1_1001_S1"))
+        );
+
+        // A lane that lacks one of the reads can not be merged.
+        std::fs::remove_file(temp_dir.path().join("P1_1001_S1_L002_R3_001.fastq.gz")).unwrap();
+        let samples = discover_samples(temp_dir.path(), "R2", true, false).unwrap();
+        assert_eq!(samples[0].0, "P1_1001_S1");
+        assert!(samples[0]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("the reads were not found for the same number of lanes"));
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_discover_samples_with_umi_in_index_read() {
+        let temp_dir = create_run_dir(&[
+            "P1_1001_S1_L001_R1_001.fastq.gz",
+            "P1_1001_S1_L001_R2_001.fastq.gz",
+            "P1_1001_S1_L001_I1_001.fastq.gz",
+            "P1_1001_S1_L001_I2_001.fastq.gz",
+        ]);
+
+        let samples = complete_samples(temp_dir.path(), "I1", false, false);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(
+            samples[0].r2_in,
+            vec![temp_dir.path().join("P1_1001_S1_L001_R2_001.fastq.gz")]
+        );
+        assert_eq!(
+            samples[0].ru_in,
+            vec![temp_dir.path().join("P1_1001_S1_L001_I1_001.fastq.gz")]
+        );
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_discover_samples_reports_incomplete_groups() {
+        let temp_dir = create_run_dir(&[
+            "P1_1001_S1_L001_R1_001.fastq.gz",
+            "P1_1001_S1_L001_R3_001.fastq.gz",
+            "P1_1002_S2_L001_R1_001.fastq.gz",
+            "P1_1002_S2_L001_R2_001.fastq.gz",
+            "P1_1002_S2_L001_R3_001.fastq.gz",
+        ]);
+
+        // The incomplete group does not prevent the discovery of the other samples.
+        let samples = discover_samples(temp_dir.path(), "R2", false, false).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].0, "P1_1001_S1_L001");
+        assert!(samples[0]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("P1_1001_S1_L001 is incomplete: no R2 file"));
+        assert_eq!(samples[1].0, "P1_1002_S2_L001");
+        assert!(samples[1].1.is_ok());
+
+        let samples = discover_samples(temp_dir.path(), "R3", false, false).unwrap();
+        assert!(samples[0]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("expected exactly two read files besides R3"));
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_samples_ignores_symlink_cycles() {
+        let temp_dir = create_run_dir(&[
+            "project/P1_1001_S1_R1_001.fastq.gz",
+            "project/P1_1001_S1_R2_001.fastq.gz",
+            "project/P1_1001_S1_R3_001.fastq.gz",
+        ]);
+        // A link back to the run directory and a second path to the project.
+        std::os::unix::fs::symlink(temp_dir.path(), temp_dir.path().join("project/run")).unwrap();
+        std::os::unix::fs::symlink(
+            temp_dir.path().join("project"),
+            temp_dir.path().join("z_project"),
+        )
+        .unwrap();
+
+        let samples = complete_samples(temp_dir.path(), "R2", false, false);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(
+            samples[0].r1_in,
+            vec![temp_dir.path().join("project/P1_1001_S1_R1_001.fastq.gz")]
+        );
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+}
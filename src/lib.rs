@@ -0,0 +1,19 @@
+// Core of umi-transfer, which can be embedded in other tools. The command line interface in
+// main.rs is a thin wrapper around UmiTransfer.
+pub mod auxiliary;
+mod checkpoint;
+pub mod file_io;
+pub mod header_template;
+pub mod quality;
+pub mod read_editing;
+mod read_header;
+pub mod transfer;
+pub mod umi_errors;
+pub mod umi_source;
+
+pub use file_io::OverwritePolicy;
+pub use quality::QualityEncoding;
+pub use read_editing::{ExistingUMI, InlineEnd, InlineReads, UMIDestination};
+pub use transfer::{TransferSummary, UmiTransfer};
+pub use umi_errors::RuntimeErrors;
+pub use umi_source::{Umi, UmiInput, UmiRead, UmiSource};
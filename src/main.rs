@@ -6,14 +6,18 @@ use owo_colors::{OwoColorize, Stream::Stderr, Stream::Stdout};
 
 use std::process;
 
-use crate::auxiliary::timedrun;
+use crate::config::OptsConfig;
+use crate::umi_batch::OptsBatch;
 use crate::umi_external::OptsExternal;
+use crate::umi_validate::OptsValidate;
+use umi_transfer::auxiliary::timedrun;
+use umi_transfer::umi_errors;
 ///use crate::umi_internal::OptsInternal;
-mod auxiliary;
-mod file_io;
-mod read_editing;
-mod umi_errors;
+mod config;
+mod illumina;
+mod umi_batch;
 mod umi_external;
+mod umi_validate;
 
 const LOGO: &str = r#"
 ░░░░░░░░░░░░░░░░░░░░░░░░░░░ SciLifeLab - National Genomics Infrastructure ░░░░░░░░░░░░░░░░░░░░░░░░░░░                                              
@@ -42,6 +46,12 @@ pub struct Opt {
 enum Subcommand {
     /// Integrate UMIs from a separate FastQ file.
     External(OptsExternal),
+    /// Check that the input FastQ files are consistent without writing any output.
+    Validate(OptsValidate),
+    /// Integrate UMIs for all samples listed in a sample sheet or found in an Illumina output directory.
+    Batch(OptsBatch),
+    /// Inspect the settings resulting from configuration file, environment variables and command line.
+    Config(OptsConfig),
     // Extract UMIs from the reads themselves.
     // Internal(OptsInternal),
 }
@@ -74,24 +84,40 @@ fn main() {
                     err.render()
                         .if_supports_color(Stderr, |text| text.fg_rgb::<0xA7, 0xC9, 0x47>())
                 );
-                process::exit(1);
+                process::exit(umi_errors::EXIT_USAGE);
             }
         };
     });
 
     timedrun("umi-transfer finished", || {
+        // The subcommands print their own summaries, so only failures are handled here.
         let res = match opt.cmd {
-            Subcommand::External(arg) => {
-                umi_external::run(arg).context("Failed to include the UMIs")
-            } //Subcommand::Internal(arg) => umi_internal::run(arg),
+            Subcommand::External(arg) => umi_external::run(arg)
+                .map(|_| ())
+                .context("Failed to include the UMIs"),
+            Subcommand::Validate(arg) => umi_validate::run(arg)
+                .map(|_| ())
+                .context("Failed to validate the input files"),
+            Subcommand::Batch(arg) => umi_batch::run(arg)
+                .map(|_| ())
+                .context("Failed to process the batch"),
+            Subcommand::Config(arg) => config::run(arg)
+                .map(|_| ())
+                .context("Failed to show the configuration"),
+            //Subcommand::Internal(arg) => umi_internal::run(arg),
         };
 
         if let Err(err) = res {
-            eprintln!(
-                "{:?}",
-                err.if_supports_color(Stderr, |text| text.fg_rgb::<0xA7, 0xC9, 0x47>())
-            );
-            process::exit(1);
+            let code = umi_errors::exit_code(&err);
+            // If the reader of an output has stopped consuming it, the run ends quietly like other
+            // command line tools.
+            if code != umi_errors::EXIT_BROKEN_PIPE {
+                eprintln!(
+                    "{:?}",
+                    err.if_supports_color(Stderr, |text| text.fg_rgb::<0xA7, 0xC9, 0x47>())
+                );
+            }
+            process::exit(code);
         }
     });
 }
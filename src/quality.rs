@@ -0,0 +1,178 @@
+use anyhow::Result;
+use bio::io::fastq::Record;
+use std::{fs, path::PathBuf};
+
+use crate::file_io;
+
+// Number of records at the start of an input that are used to detect its quality encoding.
+pub const DETECTION_SAMPLE: usize = 10_000;
+
+// Offset of the quality characters, i.e. the character of quality score 0.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityEncoding {
+    // '!' is Q0, used by Sanger and Illumina 1.8 and later.
+    Phred33,
+    // '@' is Q0, used by Illumina 1.3 to 1.7, e.g. on the HiSeq 2000.
+    Phred64,
+}
+
+impl std::fmt::Display for QualityEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QualityEncoding::Phred33 => write!(f, "phred33"),
+            QualityEncoding::Phred64 => write!(f, "phred64"),
+        }
+    }
+}
+
+impl QualityEncoding {
+    fn offset(self) -> i16 {
+        match self {
+            QualityEncoding::Phred33 => 33,
+            QualityEncoding::Phred64 => 64,
+        }
+    }
+
+    // Decides on the encoding from the range of quality characters in a sample of records.
+    // Characters below ';' (the lowest Solexa+64 score) only occur with Phred+33, characters above
+    // 'K' (Q42 in Phred+33) only with Phred+64. If all qualities are in between, both are possible.
+    fn classify(min: u8, max: u8) -> Option<Self> {
+        if min < b';' {
+            Some(QualityEncoding::Phred33)
+        } else if max > b'K' {
+            Some(QualityEncoding::Phred64)
+        } else {
+            None
+        }
+    }
+}
+
+// Detects the quality encoding of an input from the first records of its first file. Returns
+// None if the qualities fit both encodings, or if the input is not a regular file, e.g. a FIFO,
+// which can not be read twice.
+pub fn detect_encoding(paths: &[PathBuf]) -> Result<Option<QualityEncoding>> {
+    let Some(path) = paths.first() else {
+        return Ok(None);
+    };
+    if !fs::metadata(path).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(None);
+    }
+    let mut range: Option<(u8, u8)> = None;
+    for record in file_io::read_fastqs(std::slice::from_ref(path), false)?.take(DETECTION_SAMPLE) {
+        for &qual in record?.qual() {
+            range = Some(range.map_or((qual, qual), |(min, max)| (min.min(qual), max.max(qual))));
+        }
+    }
+    Ok(range.and_then(|(min, max)| QualityEncoding::classify(min, max)))
+}
+
+// Conversion of the qualities of one input to another encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityConversion {
+    from: QualityEncoding,
+    to: QualityEncoding,
+}
+
+impl QualityConversion {
+    // Returns None if the qualities are already in the target encoding.
+    pub fn new(from: QualityEncoding, to: QualityEncoding) -> Option<Self> {
+        (from != to).then_some(QualityConversion { from, to })
+    }
+
+    // Scores without equivalent in the target encoding, e.g. the negative Solexa scores, are
+    // clamped to the printable range of the target encoding.
+    pub fn convert(&self, qual: &[u8]) -> Vec<u8> {
+        qual.iter()
+            .map(|&qual| {
+                (i16::from(qual) - self.from.offset() + self.to.offset())
+                    .clamp(self.to.offset(), i16::from(b'~')) as u8
+            })
+            .collect()
+    }
+
+    pub fn convert_record(&self, record: &Record) -> Record {
+        Record::with_attrs(
+            record.id(),
+            record.desc(),
+            record.seq(),
+            &self.convert(record.qual()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::fixture::TempDir;
+
+    #[test]
+    fn test_classify_quality_range() {
+        // Illumina 1.8: '#' is Q2, 'J' is Q41.
+        assert_eq!(
+            QualityEncoding::classify(b'#', b'J'),
+            Some(QualityEncoding::Phred33)
+        );
+        // Illumina 1.5: 'B' is Q2, 'h' is Q40.
+        assert_eq!(
+            QualityEncoding::classify(b'B', b'h'),
+            Some(QualityEncoding::Phred64)
+        );
+        // Solexa scores start at ';'.
+        assert_eq!(
+            QualityEncoding::classify(b';', b'h'),
+            Some(QualityEncoding::Phred64)
+        );
+        // High Phred+33 or low Phred+64 scores only.
+        assert_eq!(QualityEncoding::classify(b'@', b'I'), None);
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(
+            detect_encoding(&[PathBuf::from("tests/seqdata/read1.fq.gz")]).unwrap(),
+            Some(QualityEncoding::Phred33)
+        );
+
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let path = temp_dir.path().join("hiseq.fq");
+        fs::write(&path, "@READ1\nACGT\n+\nhhgB\n@READ2\nACGT\n+\nhhhh\n").unwrap();
+        assert_eq!(
+            detect_encoding(std::slice::from_ref(&path)).unwrap(),
+            Some(QualityEncoding::Phred64)
+        );
+
+        // Special files are not sampled, as their records would be lost for the transfer.
+        assert_eq!(
+            detect_encoding(&[PathBuf::from("/dev/null")]).unwrap(),
+            None
+        );
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_convert_qualities() {
+        let to_phred33 =
+            QualityConversion::new(QualityEncoding::Phred64, QualityEncoding::Phred33).unwrap();
+        assert_eq!(to_phred33.convert(b"@Bh;"), b"!#I!");
+
+        let to_phred64 =
+            QualityConversion::new(QualityEncoding::Phred33, QualityEncoding::Phred64).unwrap();
+        assert_eq!(to_phred64.convert(b"!#I"), b"@Bh");
+
+        assert_eq!(
+            QualityConversion::new(QualityEncoding::Phred33, QualityEncoding::Phred33),
+            None
+        );
+
+        let record = Record::with_attrs("READ1", Some("1:N:0:1"), b"ACGT", b"hhgB");
+        let converted = to_phred33.convert_record(&record);
+        assert_eq!(converted.qual(), b"IIH#");
+        assert_eq!(converted.desc(), Some("1:N:0:1"));
+        assert_eq!(converted.seq(), b"ACGT");
+    }
+}
@@ -1,30 +1,196 @@
-#[derive(clap::ValueEnum, Clone, Debug)]
+use anyhow::anyhow;
+
+use crate::header_template::HeaderTemplate;
+use crate::read_header::{split_read_suffix, ReadHeader};
+use crate::umi_errors::RuntimeErrors;
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum UMIDestination {
     Header,
     Inline,
 }
 
+// How to handle reads whose name already ends with a UMI, e.g. when a `_with_UMIs` file is processed again.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingUMI {
+    Error,
+    Replace,
+    Append,
+    Skip,
+}
+
+// Returns the read name without the UMI, if the name ends with the separator followed by a UMI
+// of the given length. UMIs added by an earlier run with the same UMI reads have the same length,
+// which avoids taking a short name field like `:A` for a UMI.
+pub fn strip_existing_umi<'a>(id: &'a str, separator: &str, umi_len: usize) -> Option<&'a str> {
+    if separator.is_empty() || umi_len == 0 {
+        return None;
+    }
+    let (name, umi) = id.rsplit_once(separator)?;
+    let is_umi = umi.len() == umi_len
+        && umi
+            .bytes()
+            .all(|b| matches!(b, b'A' | b'C' | b'G' | b'T' | b'N'));
+    is_umi.then_some(name)
+}
+
+// Name of a read without a legacy read number suffix like `/1`, so that all reads of a pair share it.
+pub fn pair_name(id: &str) -> &str {
+    split_read_suffix(id).0
+}
+
+// Checks that a read belongs to a UMI record. Reads that already carry a UMI in their name match as
+// well. Legacy read number suffixes are ignored, as they differ between the reads and the UMIs.
+pub fn same_read(read_id: &str, umi_id: &str, separator: &str, umi_len: usize) -> bool {
+    let umi_name = pair_name(umi_id);
+    let read_name = pair_name(read_id);
+    read_name == umi_name
+        || strip_existing_umi(read_name, separator, umi_len).map(pair_name) == Some(umi_name)
+}
+
 // Updates the header and description of the reads accordingly
 pub fn umi_to_record_header(
     input: bio::io::fastq::Record,
     umi: &[u8],
-    umi_sep: Option<&String>,
-    edit_nr: Option<u8>,
+    umi_qual: &[u8],
+    template: &HeaderTemplate,
+    read_nr: u8,
+    edit_nr: bool,
+    on_existing_umi: &ExistingUMI,
 ) -> Result<bio::io::fastq::Record, anyhow::Error> {
-    let delim = umi_sep.as_ref().map(|s| s.as_str()).unwrap_or(":"); // the delimiter for the UMI
-    let new_id = &[input.id(), delim, std::str::from_utf8(umi).unwrap()].concat();
-    if let Some(number) = edit_nr {
-        let mut new_desc = String::from(input.desc().unwrap());
-        new_desc.replace_range(0..1, &number.to_string());
-        let desc: Option<&str> = Some(&new_desc);
-        let new_record =
-            bio::io::fastq::Record::with_attrs(new_id, desc, input.seq(), input.qual());
-        Ok(new_record)
+    let (mut name, suffix) = split_read_suffix(input.id());
+    let mut add_umi = true;
+    let separator = template.umi_separator().unwrap_or_default();
+    if let Some(stripped) = strip_existing_umi(name, separator, umi.len()) {
+        match on_existing_umi {
+            ExistingUMI::Error => {
+                return Err(anyhow!(RuntimeErrors::UMIExists(input.id().to_string())))
+            }
+            ExistingUMI::Replace => name = stripped,
+            ExistingUMI::Append => {}
+            ExistingUMI::Skip => add_umi = false,
+        }
+    }
+
+    let id = format!("{}{}", name, suffix);
+    let (id, desc) = if edit_nr {
+        ReadHeader::parse(&id, input.desc())?.with_read_nr(read_nr)
     } else {
-        let new_record =
-            bio::io::fastq::Record::with_attrs(new_id, input.desc(), input.seq(), input.qual());
-        Ok(new_record)
+        (id, input.desc().map(str::to_string))
+    };
+    // A legacy read number suffix stays at the end of the read name, after the UMI.
+    let (id, desc) = if add_umi {
+        let (name, suffix) = split_read_suffix(&id);
+        let (name, desc) = template.render(name, desc.as_deref(), umi, umi_qual, read_nr)?;
+        (format!("{}{}", name, suffix), desc)
+    } else {
+        (id, desc)
+    };
+    let new_record =
+        bio::io::fastq::Record::with_attrs(&id, desc.as_deref(), input.seq(), input.qual());
+    Ok(new_record)
+}
+
+// End of the read sequence to which an inline UMI is added.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Debug, PartialEq)]
+pub enum InlineEnd {
+    #[value(name = "5p")]
+    #[serde(rename = "5p")]
+    FivePrime,
+    #[value(name = "3p")]
+    #[serde(rename = "3p")]
+    ThreePrime,
+}
+
+// Reads that receive an inline UMI.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InlineReads {
+    R1,
+    R2,
+    Both,
+}
+
+// Placement of an inline UMI and the optional spacer between UMI and insert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineLayout {
+    pub end: InlineEnd,
+    pub reads: InlineReads,
+    pub spacer: Vec<u8>,
+    pub spacer_qual: u8,
+}
+
+impl Default for InlineLayout {
+    fn default() -> Self {
+        InlineLayout {
+            end: InlineEnd::FivePrime,
+            reads: InlineReads::Both,
+            spacer: Vec::new(),
+            spacer_qual: b'I',
+        }
+    }
+}
+
+impl InlineLayout {
+    // Validates the spacer, so that mistakes are reported before any file is written.
+    pub fn new(
+        end: InlineEnd,
+        reads: InlineReads,
+        spacer: Option<&str>,
+        spacer_qual: Option<char>,
+    ) -> Result<Self, anyhow::Error> {
+        let spacer = spacer.unwrap_or_default();
+        if !spacer
+            .bytes()
+            .all(|b| matches!(b, b'A' | b'C' | b'G' | b'T' | b'N'))
+        {
+            return Err(anyhow!(RuntimeErrors::SpacerInvalid(format!(
+                "spacer sequence {} may only contain A, C, G, T and N",
+                spacer
+            ))));
+        }
+        let spacer_qual = spacer_qual.unwrap_or('I');
+        if !('!'..='~').contains(&spacer_qual) {
+            return Err(anyhow!(RuntimeErrors::SpacerInvalid(format!(
+                "spacer quality '{}' is not a valid quality character",
+                spacer_qual
+            ))));
+        }
+        Ok(InlineLayout {
+            end,
+            reads,
+            spacer: spacer.as_bytes().to_vec(),
+            spacer_qual: spacer_qual as u8,
+        })
     }
+
+    // Checks whether the UMI is added to read 1 or 2.
+    pub fn includes(&self, read_nr: u8) -> bool {
+        match self.reads {
+            InlineReads::R1 => read_nr == 1,
+            InlineReads::R2 => read_nr == 2,
+            InlineReads::Both => true,
+        }
+    }
+}
+
+// Updates only the read number of reads that do not receive a UMI.
+pub fn correct_read_nr(
+    input: bio::io::fastq::Record,
+    edit_nr: Option<u8>,
+) -> Result<bio::io::fastq::Record, anyhow::Error> {
+    let Some(number) = edit_nr else {
+        return Ok(input);
+    };
+    let (id, desc) = ReadHeader::parse(input.id(), input.desc())?.with_read_nr(number);
+    Ok(bio::io::fastq::Record::with_attrs(
+        &id,
+        desc.as_deref(),
+        input.seq(),
+        input.qual(),
+    ))
 }
 
 // Updates the header and description of the reads accordingly
@@ -32,26 +198,41 @@ pub fn umi_to_record_seq(
     input: bio::io::fastq::Record,
     umi: &[u8],
     umi_qual: &[u8],
+    layout: &InlineLayout,
     edit_nr: Option<u8>,
 ) -> Result<bio::io::fastq::Record, anyhow::Error> {
-    let mut concatenated_seq_str = String::with_capacity(umi.len() + input.seq().len());
-    concatenated_seq_str.push_str(std::str::from_utf8(umi)?);
-    concatenated_seq_str.push_str(std::str::from_utf8(input.seq())?);
+    let spacer_qual = vec![layout.spacer_qual; layout.spacer.len()];
+    let (seq_parts, qual_parts) = match layout.end {
+        InlineEnd::FivePrime => (
+            [umi, &layout.spacer, input.seq()],
+            [umi_qual, &spacer_qual, input.qual()],
+        ),
+        InlineEnd::ThreePrime => (
+            [input.seq(), &layout.spacer, umi],
+            [input.qual(), &spacer_qual, umi_qual],
+        ),
+    };
 
-    let mut concatenated_qual_str = String::with_capacity(umi_qual.len() + input.qual().len());
-    concatenated_qual_str.push_str(std::str::from_utf8(umi_qual)?);
-    concatenated_qual_str.push_str(std::str::from_utf8(input.qual())?);
+    let mut concatenated_seq_str =
+        String::with_capacity(umi.len() + layout.spacer.len() + input.seq().len());
+    for part in seq_parts {
+        concatenated_seq_str.push_str(std::str::from_utf8(part)?);
+    }
+
+    let mut concatenated_qual_str =
+        String::with_capacity(umi_qual.len() + spacer_qual.len() + input.qual().len());
+    for part in qual_parts {
+        concatenated_qual_str.push_str(std::str::from_utf8(part)?);
+    }
 
     if let Some(number) = edit_nr {
-        let mut new_desc = String::from(input.desc().unwrap());
-        new_desc.replace_range(0..1, &number.to_string());
-        let desc: Option<&str> = Some(&new_desc);
+        let (id, desc) = ReadHeader::parse(input.id(), input.desc())?.with_read_nr(number);
         // Unnecessary conversion to bytes and back to String, but Record::new() does
         // not take arguments and the fields of struct `bio::io::fastq::Record` are private,
         // so I can't implement another method to create a new record.
         let new_record = bio::io::fastq::Record::with_attrs(
-            input.id(),
-            desc,
+            &id,
+            desc.as_deref(),
             concatenated_seq_str.as_bytes(),
             concatenated_qual_str.as_bytes(),
         );
@@ -70,6 +251,7 @@ pub fn umi_to_record_seq(
 mod tests {
 
     use super::*;
+    use crate::header_template::DEFAULT_TEMPLATE;
 
     #[test]
     fn test_umi_to_record_header_with_edits() {
@@ -80,10 +262,19 @@ mod tests {
             b"FFFFFFFFFFF",
         );
         let umi = b"ACCAGCTA";
-        let umi_sep = "_".to_string();
-        let edit_nr = Some(5);
+        let umi_qual = b"########";
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, "_", None).unwrap();
 
-        let result = umi_to_record_header(input, umi, Some(&umi_sep), edit_nr).unwrap();
+        let result = umi_to_record_header(
+            input,
+            umi,
+            umi_qual,
+            &template,
+            5,
+            true,
+            &ExistingUMI::Error,
+        )
+        .unwrap();
         assert_eq!(
             result.id(),
             "@SCILIFELAB:500:NGISTLM:1:1101:2446:1031_ACCAGCTA"
@@ -102,9 +293,19 @@ mod tests {
             b"FFFFFFFFFFF",
         );
         let umi = b"ACCAGCTA";
-        let umi_sep = ":".to_string();
+        let umi_qual = b"########";
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, ":", None).unwrap();
 
-        let result = umi_to_record_header(input, umi, Some(&umi_sep), None).unwrap();
+        let result = umi_to_record_header(
+            input,
+            umi,
+            umi_qual,
+            &template,
+            1,
+            false,
+            &ExistingUMI::Error,
+        )
+        .unwrap();
         assert_eq!(
             result.id(),
             "@SCILIFELAB:500:NGISTLM:1:1101:2446:1031:ACCAGCTA"
@@ -114,6 +315,270 @@ mod tests {
         assert_eq!(result.qual(), b"FFFFFFFFFFF");
     }
 
+    #[test]
+    fn test_umi_to_record_header_with_edits_legacy_suffix() {
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"########";
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, ":", None).unwrap();
+
+        // The UMI goes before the read number, so that both mates keep the same name.
+        let mate = |id: &str, read_nr: u8, edit_nr: bool| {
+            let input =
+                bio::io::fastq::Record::with_attrs(id, None, b"TCGTTTTCCGC", b"FFFFFFFFFFF");
+            umi_to_record_header(
+                input,
+                umi,
+                umi_qual,
+                &template,
+                read_nr,
+                edit_nr,
+                &ExistingUMI::Error,
+            )
+            .unwrap()
+        };
+
+        let r1 = mate("HWUSI-EAS100R:6:73:941:1973#0/1", 1, true);
+        let r2 = mate("HWUSI-EAS100R:6:73:941:1973#0/3", 2, true);
+        assert_eq!(r1.id(), "HWUSI-EAS100R:6:73:941:1973#0:ACCAGCTA/1");
+        assert_eq!(r2.id(), "HWUSI-EAS100R:6:73:941:1973#0:ACCAGCTA/2");
+        assert_eq!(r1.desc(), None);
+        assert_eq!(pair_name(r1.id()), pair_name(r2.id()));
+
+        let r2 = mate("HWUSI-EAS100R:6:73:941:1973#0/3", 2, false);
+        assert_eq!(r2.id(), "HWUSI-EAS100R:6:73:941:1973#0:ACCAGCTA/3");
+
+        // A UMI that was added before the read number is detected as well.
+        let input = bio::io::fastq::Record::with_attrs(
+            "HWUSI-EAS100R:6:73:941:1973#0:GGTTAACC/1",
+            None,
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let result = umi_to_record_header(
+            input,
+            umi,
+            umi_qual,
+            &template,
+            1,
+            true,
+            &ExistingUMI::Replace,
+        )
+        .unwrap();
+        assert_eq!(result.id(), "HWUSI-EAS100R:6:73:941:1973#0:ACCAGCTA/1");
+    }
+
+    #[test]
+    fn test_umi_to_record_header_with_edits_unsupported_comment() {
+        let input = bio::io::fastq::Record::with_attrs(
+            "SRR001666.1",
+            Some("length=11"),
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"########";
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, ":", None).unwrap();
+
+        let result = umi_to_record_header(
+            input.clone(),
+            umi,
+            umi_qual,
+            &template,
+            1,
+            true,
+            &ExistingUMI::Error,
+        );
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("The read number of header 'SRR001666.1 length=11' could not be identified"));
+
+        // Without correction of the read numbers, the header is not inspected.
+        let result = umi_to_record_header(
+            input,
+            umi,
+            umi_qual,
+            &template,
+            1,
+            false,
+            &ExistingUMI::Error,
+        )
+        .unwrap();
+        assert_eq!(result.id(), "SRR001666.1:ACCAGCTA");
+        assert_eq!(result.desc(), Some("length=11"));
+    }
+
+    #[test]
+    fn test_umi_to_record_header_with_template() {
+        let input = bio::io::fastq::Record::with_attrs(
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031",
+            Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"),
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"FFFF#FFF";
+        let template =
+            HeaderTemplate::new("{id} RX:Z:{umi} QX:Z:{umi_qual} {desc}", ":", None).unwrap();
+
+        let result = umi_to_record_header(
+            input,
+            umi,
+            umi_qual,
+            &template,
+            2,
+            true,
+            &ExistingUMI::Error,
+        )
+        .unwrap();
+        assert_eq!(result.id(), "SCILIFELAB:500:NGISTLM:1:1101:2446:1031");
+        assert_eq!(
+            result.desc(),
+            Some("RX:Z:ACCAGCTA QX:Z:FFFF#FFF 2:N:0:GCTTCAGGGT+AAGGTAGCGT")
+        );
+        assert_eq!(result.seq(), b"TCGTTTTCCGC");
+    }
+
+    #[test]
+    fn test_strip_existing_umi() {
+        assert_eq!(
+            strip_existing_umi("SCILIFELAB:500:NGISTLM:1:1101:2446:1031:ACCAGCTN", ":", 8),
+            Some("SCILIFELAB:500:NGISTLM:1:1101:2446:1031")
+        );
+        assert_eq!(
+            strip_existing_umi("SCILIFELAB:500:NGISTLM:1:1101:2446:1031", ":", 8),
+            None
+        );
+        assert_eq!(
+            strip_existing_umi("SCILIFELAB:500:NGISTLM:1:1101:2446:1031:ACCAGCTA", "_", 8),
+            None
+        );
+        assert_eq!(strip_existing_umi("READ_ACCAGCTA", "", 8), None);
+        assert_eq!(strip_existing_umi("READ_ACCAGCTA", "_", 8), Some("READ"));
+        // Only a UMI of the expected length is recognized.
+        assert_eq!(strip_existing_umi("READ:A", ":", 8), None);
+        assert_eq!(strip_existing_umi("READ:ACCAGCTA", ":", 6), None);
+    }
+
+    #[test]
+    fn test_same_read() {
+        assert!(same_read("READ:1101:2446", "READ:1101:2446", ":", 8));
+        assert!(same_read(
+            "READ:1101:2446:ACCAGCTA",
+            "READ:1101:2446",
+            ":",
+            8
+        ));
+        assert!(!same_read(
+            "READ:1101:2446:ACCAGCTA",
+            "READ:1101:2447",
+            ":",
+            8
+        ));
+        assert!(!same_read(
+            "READ:1101:2446",
+            "READ:1101:2446:ACCAGCTA",
+            ":",
+            8
+        ));
+        assert!(same_read("READ:1101:2446/1", "READ:1101:2446/3", ":", 8));
+        assert!(same_read("READ:1101:2446/2", "READ:1101:2446", ":", 8));
+        assert!(same_read(
+            "READ:1101:2446/1:ACCAGCTA",
+            "READ:1101:2446/2",
+            ":",
+            8
+        ));
+        assert!(same_read(
+            "READ:1101:2446:ACCAGCTA/1",
+            "READ:1101:2446/2",
+            ":",
+            8
+        ));
+        assert!(!same_read("READ:1101:2446/1", "READ:1101:2447/2", ":", 8));
+
+        // A name field that merely looks like a short UMI does not make different reads match.
+        assert!(!same_read("X:A", "X", ":", 8));
+        assert!(!same_read("X:A/1", "X/2", ":", 8));
+        // Without separator, e.g. for templates that put the UMI into the comment, names must be equal.
+        assert!(!same_read("READ:ACCAGCTA", "READ", "", 8));
+    }
+
+    #[test]
+    fn test_pair_name() {
+        assert_eq!(pair_name("READ/1"), "READ");
+        assert_eq!(pair_name("READ/3"), "READ");
+        assert_eq!(pair_name("READ"), "READ");
+        assert_eq!(pair_name("READ/12"), "READ/12");
+        assert_eq!(pair_name("LANE/A"), "LANE/A");
+    }
+
+    #[test]
+    fn test_umi_to_record_header_with_existing_umi() {
+        let input = bio::io::fastq::Record::with_attrs(
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031:GGTTAACC",
+            Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"),
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"########";
+        let template = HeaderTemplate::new(DEFAULT_TEMPLATE, ":", None).unwrap();
+
+        let result = umi_to_record_header(
+            input.clone(),
+            umi,
+            umi_qual,
+            &template,
+            1,
+            false,
+            &ExistingUMI::Error,
+        );
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("SCILIFELAB:500:NGISTLM:1:1101:2446:1031:GGTTAACC already contains a UMI"));
+
+        let result = umi_to_record_header(
+            input.clone(),
+            umi,
+            umi_qual,
+            &template,
+            1,
+            false,
+            &ExistingUMI::Replace,
+        )
+        .unwrap();
+        assert_eq!(
+            result.id(),
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031:ACCAGCTA"
+        );
+
+        let result = umi_to_record_header(
+            input.clone(),
+            umi,
+            umi_qual,
+            &template,
+            1,
+            false,
+            &ExistingUMI::Append,
+        )
+        .unwrap();
+        assert_eq!(
+            result.id(),
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031:GGTTAACC:ACCAGCTA"
+        );
+
+        let result =
+            umi_to_record_header(input, umi, umi_qual, &template, 2, true, &ExistingUMI::Skip)
+                .unwrap();
+        assert_eq!(
+            result.id(),
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031:GGTTAACC"
+        );
+        assert_eq!(result.desc(), Some("2:N:0:GCTTCAGGGT+AAGGTAGCGT"));
+    }
+
     #[test]
     fn test_umi_to_record_seq_with_edit_nr() {
         let input = bio::io::fastq::Record::with_attrs(
@@ -126,13 +591,28 @@ mod tests {
         let umi_qual = b"########";
         let edit_nr = Some(5);
 
-        let result = umi_to_record_seq(input, umi, umi_qual, edit_nr).unwrap();
+        let result =
+            umi_to_record_seq(input, umi, umi_qual, &InlineLayout::default(), edit_nr).unwrap();
         assert_eq!(result.id(), "@SCILIFELAB:500:NGISTLM:1:1101:2446:1031");
         assert_eq!(result.desc(), Some("5:N:0:GCTTCAGGGT+AAGGTAGCGT"));
         assert_eq!(result.seq(), b"ACCAGCTATCGTTTTCCGC");
         assert_eq!(result.qual(), b"########FFFFFFFFFFF");
     }
 
+    #[test]
+    fn test_umi_to_record_seq_with_edit_nr_without_comment() {
+        let input =
+            bio::io::fastq::Record::with_attrs("SRR001666.1", None, b"TCGTTTTCCGC", b"FFFFFFFFFFF");
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"########";
+
+        let result =
+            umi_to_record_seq(input, umi, umi_qual, &InlineLayout::default(), Some(2)).unwrap();
+        assert_eq!(result.id(), "SRR001666.1");
+        assert_eq!(result.desc(), None);
+        assert_eq!(result.seq(), b"ACCAGCTATCGTTTTCCGC");
+    }
+
     #[test]
     fn test_umi_to_record_seq_without_edit_nr() {
         let input = bio::io::fastq::Record::with_attrs(
@@ -144,10 +624,77 @@ mod tests {
         let umi = b"ACCAGCTA";
         let umi_qual = b"########";
 
-        let result = umi_to_record_seq(input, umi, umi_qual, None).unwrap();
+        let result =
+            umi_to_record_seq(input, umi, umi_qual, &InlineLayout::default(), None).unwrap();
         assert_eq!(result.id(), "@SCILIFELAB:500:NGISTLM:1:1101:2446:1031");
         assert_eq!(result.desc(), Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"));
         assert_eq!(result.seq(), b"ACCAGCTATCGTTTTCCGC");
         assert_eq!(result.qual(), b"########FFFFFFFFFFF");
     }
+
+    #[test]
+    fn test_umi_to_record_seq_three_prime_with_spacer() {
+        let input = bio::io::fastq::Record::with_attrs(
+            "@SCILIFELAB:500:NGISTLM:1:1101:2446:1031",
+            Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"),
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let umi = b"ACCAGCTA";
+        let umi_qual = b"########";
+        let layout = InlineLayout::new(
+            InlineEnd::ThreePrime,
+            InlineReads::Both,
+            Some("NN"),
+            Some('!'),
+        )
+        .unwrap();
+
+        let result = umi_to_record_seq(input.clone(), umi, umi_qual, &layout, None).unwrap();
+        assert_eq!(result.seq(), b"TCGTTTTCCGCNNACCAGCTA");
+        assert_eq!(result.qual(), b"FFFFFFFFFFF!!########");
+
+        let layout =
+            InlineLayout::new(InlineEnd::FivePrime, InlineReads::Both, Some("GT"), None).unwrap();
+        let result = umi_to_record_seq(input, umi, umi_qual, &layout, None).unwrap();
+        assert_eq!(result.seq(), b"ACCAGCTAGTTCGTTTTCCGC");
+        assert_eq!(result.qual(), b"########IIFFFFFFFFFFF");
+    }
+
+    #[test]
+    fn test_inline_layout() {
+        let layout = InlineLayout::new(InlineEnd::FivePrime, InlineReads::R1, None, None).unwrap();
+        assert!(layout.includes(1));
+        assert!(!layout.includes(2));
+        assert!(layout.spacer.is_empty());
+
+        let result = InlineLayout::new(InlineEnd::FivePrime, InlineReads::Both, Some("ACGU"), None);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("spacer sequence ACGU may only contain A, C, G, T and N"));
+        let result = InlineLayout::new(
+            InlineEnd::FivePrime,
+            InlineReads::Both,
+            Some("AC"),
+            Some(' '),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_correct_read_nr() {
+        let input = bio::io::fastq::Record::with_attrs(
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031",
+            Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"),
+            b"TCGTTTTCCGC",
+            b"FFFFFFFFFFF",
+        );
+        let result = correct_read_nr(input.clone(), Some(2)).unwrap();
+        assert_eq!(result.desc(), Some("2:N:0:GCTTCAGGGT+AAGGTAGCGT"));
+        assert_eq!(result.seq(), b"TCGTTTTCCGC");
+
+        let result = correct_read_nr(input, None).unwrap();
+        assert_eq!(result.desc(), Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"));
+    }
 }
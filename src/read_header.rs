@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Result};
+
+use crate::umi_errors::RuntimeErrors;
+
+// Location of the read number within a FastQ header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadNumberStyle {
+    // Casava 1.8+ comment, e.g. `@M00123:8:000000000-A1B2C:1:1101:15589:1331 1:N:0:GCTTCAGG`.
+    Casava,
+    // Legacy suffix of the read name, e.g. `@HWUSI-EAS100R:6:73:941:1973#0/1`.
+    Suffix,
+    // Comment written by the SRA toolkit, e.g. `@SRR001666.1 1/1` or `@SRR001666.1 071112_SLXA-EAS1_s_7:5:1:817:345/1`.
+    Sra,
+    // Header without comment and suffix, which carries no read number.
+    Missing,
+}
+
+// FastQ header split into the parts that are relevant to edit the read number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadHeader<'a> {
+    id: &'a str,
+    desc: Option<&'a str>,
+    style: ReadNumberStyle,
+}
+
+// Returns the read number and the remaining text, if the text ends with a `/<digit>` suffix.
+fn split_suffix(text: &str) -> Option<(&str, u8)> {
+    let (head, number) = text.rsplit_once('/')?;
+    match number.as_bytes() {
+        [digit] if digit.is_ascii_digit() => Some((head, digit - b'0')),
+        _ => None,
+    }
+}
+
+// Splits a read name into the name and its legacy read number suffix like `/1`, which is empty if absent.
+pub fn split_read_suffix(id: &str) -> (&str, &str) {
+    match split_suffix(id) {
+        Some((name, _read)) => id.split_at(name.len()),
+        None => (id, ""),
+    }
+}
+
+// Checks for the `<read>:<is filtered>:<control number>:<index>` layout of Casava 1.8+ comments.
+fn is_casava(desc: &str) -> bool {
+    let mut fields = desc.split(':');
+    match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(read), Some(filtered), Some(control), Some(_index)) => {
+            !read.is_empty()
+                && read.bytes().all(|b| b.is_ascii_digit())
+                && (filtered == "Y" || filtered == "N")
+                && !control.is_empty()
+                && control.bytes().all(|b| b.is_ascii_digit())
+        }
+        _ => false,
+    }
+}
+
+// Returns the index sequence(s) of a Casava 1.8+ comment, e.g. `GCTTCAGG+AAGGTAGC`.
+pub fn casava_index(desc: Option<&str>) -> Option<&str> {
+    let desc = desc.filter(|d| is_casava(d))?;
+    let index = desc.splitn(4, ':').nth(3)?;
+    Some(split_first_word(index).0)
+}
+
+// Splits off the first word of a comment and returns it together with the rest including the separator.
+fn split_first_word(desc: &str) -> (&str, &str) {
+    match desc.find(char::is_whitespace) {
+        Some(pos) => desc.split_at(pos),
+        None => (desc, ""),
+    }
+}
+
+impl<'a> ReadHeader<'a> {
+    // Identifies where the read number is stored. Headers with a comment that does not carry
+    // a read number in one of the known styles are rejected.
+    pub fn parse(id: &'a str, desc: Option<&'a str>) -> Result<Self> {
+        let comment = desc.filter(|d| !d.trim().is_empty());
+        let style = if comment.is_some_and(is_casava) {
+            ReadNumberStyle::Casava
+        } else if split_suffix(id).is_some() {
+            ReadNumberStyle::Suffix
+        } else if comment.is_some_and(|d| split_suffix(split_first_word(d).0).is_some()) {
+            ReadNumberStyle::Sra
+        } else if comment.is_none() {
+            ReadNumberStyle::Missing
+        } else {
+            return Err(anyhow!(RuntimeErrors::UnsupportedHeader(
+                [Some(id), desc]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )));
+        };
+        Ok(ReadHeader { id, desc, style })
+    }
+
+    // Returns the id and description with the read number replaced. Headers without
+    // read number are returned unchanged.
+    pub fn with_read_nr(&self, number: u8) -> (String, Option<String>) {
+        let desc = self.desc.map(str::to_string);
+        match self.style {
+            ReadNumberStyle::Casava => {
+                let desc = self.desc.unwrap_or_default();
+                let (_read, rest) = desc.split_once(':').unwrap_or((desc, ""));
+                (self.id.to_string(), Some(format!("{}:{}", number, rest)))
+            }
+            ReadNumberStyle::Suffix => {
+                let (name, _read) = split_suffix(self.id).unwrap_or((self.id, 0));
+                (format!("{}/{}", name, number), desc)
+            }
+            ReadNumberStyle::Sra => {
+                let (word, rest) = split_first_word(self.desc.unwrap_or_default());
+                let (spot, _read) = split_suffix(word).unwrap_or((word, 0));
+                (
+                    self.id.to_string(),
+                    Some(format!("{}/{}{}", spot, number, rest)),
+                )
+            }
+            ReadNumberStyle::Missing => (self.id.to_string(), desc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_casava_header() {
+        let header = ReadHeader::parse(
+            "SCILIFELAB:500:NGISTLM:1:1101:2446:1031",
+            Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"),
+        )
+        .unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Casava);
+        assert_eq!(
+            header.with_read_nr(2),
+            (
+                "SCILIFELAB:500:NGISTLM:1:1101:2446:1031".to_string(),
+                Some("2:N:0:GCTTCAGGGT+AAGGTAGCGT".to_string())
+            )
+        );
+
+        // bcl2fastq writes the sample number instead of the index if the sample sheet has no indices.
+        let header = ReadHeader::parse(
+            "M00123:8:000000000-A1B2C:1:1101:15589:1331",
+            Some("3:Y:18:2"),
+        )
+        .unwrap();
+        assert_eq!(header.with_read_nr(1).1, Some("1:Y:18:2".to_string()));
+    }
+
+    #[test]
+    fn test_suffix_header() {
+        let header = ReadHeader::parse("HWUSI-EAS100R:6:73:941:1973#0/3", None).unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Suffix);
+        assert_eq!(
+            header.with_read_nr(2),
+            ("HWUSI-EAS100R:6:73:941:1973#0/2".to_string(), None)
+        );
+
+        // Comments without read number are kept.
+        let header =
+            ReadHeader::parse("HWUSI-EAS100R:6:73:941:1973#0/1", Some("length=36")).unwrap();
+        assert_eq!(
+            header.with_read_nr(2),
+            (
+                "HWUSI-EAS100R:6:73:941:1973#0/2".to_string(),
+                Some("length=36".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_read_suffix() {
+        assert_eq!(split_read_suffix("READ#0/1"), ("READ#0", "/1"));
+        assert_eq!(split_read_suffix("READ"), ("READ", ""));
+        // Only single digits are read numbers, like in pair_name.
+        assert_eq!(split_read_suffix("READ/12"), ("READ/12", ""));
+        assert_eq!(split_read_suffix("LANE/A"), ("LANE/A", ""));
+        assert!(ReadHeader::parse("READ/12", Some("length=36")).is_err());
+    }
+
+    #[test]
+    fn test_sra_header() {
+        let header = ReadHeader::parse("SRR001666.1", Some("1/3")).unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Sra);
+        assert_eq!(
+            header.with_read_nr(2),
+            ("SRR001666.1".to_string(), Some("1/2".to_string()))
+        );
+
+        let header = ReadHeader::parse(
+            "SRR001666.1",
+            Some("071112_SLXA-EAS1_s_7:5:1:817:345/1 length=36"),
+        )
+        .unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Sra);
+        assert_eq!(
+            header.with_read_nr(2).1,
+            Some("071112_SLXA-EAS1_s_7:5:1:817:345/2 length=36".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_read_number() {
+        let header = ReadHeader::parse("SRR001666.1", None).unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Missing);
+        assert_eq!(header.with_read_nr(2), ("SRR001666.1".to_string(), None));
+
+        let header = ReadHeader::parse("SRR001666.1", Some(" ")).unwrap();
+        assert_eq!(header.style, ReadNumberStyle::Missing);
+        assert_eq!(header.with_read_nr(2).1, Some(" ".to_string()));
+    }
+
+    #[test]
+    fn test_casava_index() {
+        assert_eq!(
+            casava_index(Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT")),
+            Some("GCTTCAGGGT+AAGGTAGCGT")
+        );
+        assert_eq!(casava_index(Some("2:Y:18:2 extra")), Some("2"));
+        assert_eq!(casava_index(Some("1/1")), None);
+        assert_eq!(casava_index(None), None);
+    }
+
+    #[test]
+    fn test_unsupported_header() {
+        let result = ReadHeader::parse("SRR001666.1", Some("length=36"));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("SRR001666.1 length=36"));
+
+        // The read number must be the first field of a Casava comment.
+        assert!(ReadHeader::parse("READ", Some("N:0:GCTTCAGG")).is_err());
+    }
+}
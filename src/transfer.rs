@@ -0,0 +1,833 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::auxiliary::{threads_available, threads_per_task};
+use crate::checkpoint::{self, Checkpoint};
+use crate::file_io::{self, OutputNaming, OverwritePolicy, RecordSink};
+use crate::header_template::{HeaderTemplate, DEFAULT_TEMPLATE};
+use crate::quality::{self, QualityConversion, QualityEncoding};
+use crate::read_editing::{
+    correct_read_nr, pair_name, umi_to_record_header, umi_to_record_seq, ExistingUMI, InlineEnd,
+    InlineLayout, InlineReads, UMIDestination,
+};
+use crate::umi_errors::RuntimeErrors;
+use crate::umi_source::{UmiInput, UmiSource};
+
+// Transfer of the UMIs from a separate FastQ file or another `UmiInput` to a pair of read files.
+// All settings are optional and default to the behaviour of `umi-transfer external` without
+// further arguments.
+//
+//     let summary = UmiTransfer::new("R1.fq.gz", "R3.fq.gz", "R2.fq.gz")
+//         .destination(UMIDestination::Header)
+//         .delim(":")
+//         .run()?;
+#[derive(Debug, Clone)]
+pub struct UmiTransfer {
+    r1_in: Vec<PathBuf>,
+    r2_in: Vec<PathBuf>,
+    umis: UmiInput,
+    r1_out: Option<PathBuf>,
+    r2_out: Option<PathBuf>,
+    ru_out: Option<PathBuf>,
+    naming: OutputNaming,
+    destination: UMIDestination,
+    edit_nr: bool,
+    delim: String,
+    header_template: String,
+    on_existing_umi: ExistingUMI,
+    inline_end: InlineEnd,
+    inline_reads: InlineReads,
+    spacer: Option<String>,
+    spacer_qual: Option<char>,
+    sample: Option<String>,
+    gzip: bool,
+    compression_level: Option<u32>,
+    threads: Option<usize>,
+    overwrite: OverwritePolicy,
+    checkpoint: Option<u64>,
+    resume: bool,
+    keep_partial: bool,
+    strict: bool,
+    convert_quals_to: Option<QualityEncoding>,
+    progress: Option<Progress>,
+    verbose: bool,
+}
+
+// Callback that is given the number of processed record pairs every `every` records.
+#[derive(Clone)]
+struct Progress {
+    every: u64,
+    callback: Arc<dyn Fn(u64) -> Result<()> + Send + Sync>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+// Result of a completed transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferSummary {
+    // Number of record pairs in the outputs, including those written before a resumed checkpoint.
+    pub records: u64,
+    // Number of record pairs that had already been written by the interrupted run.
+    pub resumed_after: u64,
+    pub output1: PathBuf,
+    pub output2: PathBuf,
+    pub output_umi: Option<PathBuf>,
+    // Uncompressed FastQ bytes written to each output by this run, i.e. without those written
+    // before a resumed checkpoint.
+    pub output1_bytes: u64,
+    pub output2_bytes: u64,
+    pub output_umi_bytes: Option<u64>,
+}
+
+impl UmiTransfer {
+    pub fn new(r1: impl Into<PathBuf>, r2: impl Into<PathBuf>, umi: impl Into<PathBuf>) -> Self {
+        Self::from_lanes(vec![r1.into()], vec![r2.into()], vec![umi.into()])
+    }
+
+    // Several files per input, e.g. lanes, are processed one after another. Every read file
+    // must be paired with a UMI file.
+    pub fn from_lanes(r1: Vec<PathBuf>, r2: Vec<PathBuf>, umi: Vec<PathBuf>) -> Self {
+        Self::with_umis(r1, r2, UmiInput::FastQ(umi))
+    }
+
+    // Transfer of UMIs that are not stored in a separate FastQ file, e.g. from a table.
+    pub fn with_umis(r1: Vec<PathBuf>, r2: Vec<PathBuf>, umis: UmiInput) -> Self {
+        UmiTransfer {
+            r1_in: r1,
+            r2_in: r2,
+            umis,
+            r1_out: None,
+            r2_out: None,
+            ru_out: None,
+            naming: OutputNaming::default(),
+            destination: UMIDestination::Header,
+            edit_nr: false,
+            delim: ":".to_string(),
+            header_template: DEFAULT_TEMPLATE.to_string(),
+            on_existing_umi: ExistingUMI::Error,
+            inline_end: InlineEnd::FivePrime,
+            inline_reads: InlineReads::Both,
+            spacer: None,
+            spacer_qual: None,
+            sample: None,
+            gzip: false,
+            compression_level: None,
+            threads: None,
+            overwrite: OverwritePolicy::Prompt,
+            checkpoint: None,
+            resume: false,
+            keep_partial: false,
+            strict: false,
+            convert_quals_to: None,
+            progress: None,
+            verbose: false,
+        }
+    }
+
+    // Output for read 1. Defaults to the first input with a `_with_UMIs` suffix, see output_naming().
+    pub fn out(mut self, path: impl Into<PathBuf>) -> Self {
+        self.r1_out = Some(path.into());
+        self
+    }
+
+    // Output for read 2. Defaults to the first input with a `_with_UMIs` suffix, see output_naming().
+    pub fn out2(mut self, path: impl Into<PathBuf>) -> Self {
+        self.r2_out = Some(path.into());
+        self
+    }
+
+    // Additional output for the UMI records, which are named like the reads.
+    pub fn out_umi(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ru_out = Some(path.into());
+        self
+    }
+
+    // Naming of outputs that are not given with out() and out2().
+    pub fn output_naming(mut self, naming: OutputNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    pub fn umis(mut self, umis: UmiInput) -> Self {
+        self.umis = umis;
+        self
+    }
+
+    pub fn destination(mut self, destination: UMIDestination) -> Self {
+        self.destination = destination;
+        self
+    }
+
+    pub fn correct_read_numbers(mut self, edit_nr: bool) -> Self {
+        self.edit_nr = edit_nr;
+        self
+    }
+
+    pub fn delim(mut self, delim: impl Into<String>) -> Self {
+        self.delim = delim.into();
+        self
+    }
+
+    pub fn header_template(mut self, template: impl Into<String>) -> Self {
+        self.header_template = template.into();
+        self
+    }
+
+    pub fn on_existing_umi(mut self, on_existing_umi: ExistingUMI) -> Self {
+        self.on_existing_umi = on_existing_umi;
+        self
+    }
+
+    pub fn inline_end(mut self, end: InlineEnd) -> Self {
+        self.inline_end = end;
+        self
+    }
+
+    pub fn inline_reads(mut self, reads: InlineReads) -> Self {
+        self.inline_reads = reads;
+        self
+    }
+
+    pub fn spacer(mut self, spacer: impl Into<String>) -> Self {
+        self.spacer = Some(spacer.into());
+        self
+    }
+
+    // Phred+33 quality character, converted along with the reads by convert_quals_to().
+    pub fn spacer_qual(mut self, spacer_qual: char) -> Self {
+        self.spacer_qual = Some(spacer_qual);
+        self
+    }
+
+    // Sample name for the {sample} placeholder of the header template.
+    pub fn sample(mut self, sample: impl Into<String>) -> Self {
+        self.sample = Some(sample.into());
+        self
+    }
+
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.compression_level = Some(level);
+        self
+    }
+
+    // Maximum number of threads. Defaults to the number of available cores.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    // Overwrite existing outputs without asking for confirmation. Same as `OverwritePolicy::Always`.
+    pub fn force(mut self, force: bool) -> Self {
+        self.overwrite = if force {
+            OverwritePolicy::Always
+        } else {
+            OverwritePolicy::Prompt
+        };
+        self
+    }
+
+    // What to do if an output exists already. Defaults to asking for confirmation on a terminal.
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    // Save the progress every given number of records, so that the run can be resumed.
+    pub fn checkpoint(mut self, every: u64) -> Self {
+        self.checkpoint = Some(every);
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn keep_partial(mut self, keep_partial: bool) -> Self {
+        self.keep_partial = keep_partial;
+        self
+    }
+
+    // Check every record of the FastQ inputs, see file_io::check_record().
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Convert the qualities of all inputs to one encoding, which is detected for every input.
+    pub fn convert_quals_to(mut self, encoding: QualityEncoding) -> Self {
+        self.convert_quals_to = Some(encoding);
+        self
+    }
+
+    // Report the number of processed record pairs to a callback every given number of records,
+    // e.g. to update a progress bar. An error of the callback aborts the run.
+    pub fn progress(
+        mut self,
+        every: u64,
+        callback: impl Fn(u64) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Progress {
+            every,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    // Print the output paths and the progress to stdout, as the command line interface does.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    fn inline_layout(&self) -> Result<InlineLayout> {
+        let mut layout = InlineLayout::new(
+            self.inline_end.clone(),
+            self.inline_reads.clone(),
+            self.spacer.as_deref(),
+            self.spacer_qual,
+        )?;
+        // The spacer quality is given in Phred+33 like the default qualities of UMIs.
+        if let Some(conversion) = self
+            .convert_quals_to
+            .and_then(|target| QualityConversion::new(QualityEncoding::Phred33, target))
+        {
+            layout.spacer_qual = conversion.convert(&[layout.spacer_qual])[0];
+        }
+        Ok(layout)
+    }
+
+    fn template(&self) -> Result<HeaderTemplate> {
+        HeaderTemplate::new(&self.header_template, &self.delim, self.sample.as_deref())
+    }
+
+    // Detects the quality encoding of every input. Inputs whose qualities fit both encodings are
+    // assumed to be Phred+33, like UMI tables and the default qualities of UMIs.
+    fn quality_conversions(
+        &self,
+        target: QualityEncoding,
+    ) -> Result<[Option<QualityConversion>; 3]> {
+        let detect = |paths: &[PathBuf]| -> Result<QualityEncoding> {
+            let encoding = quality::detect_encoding(paths).with_context(|| {
+                format!(
+                    "Failed to detect the quality encoding of {}",
+                    file_io::display_paths(paths)
+                )
+            })?;
+            if self.verbose {
+                println!(
+                    "Quality encoding of {}: {}",
+                    file_io::display_paths(paths),
+                    encoding.map_or("undetermined, assuming phred33".to_string(), |encoding| {
+                        encoding.to_string()
+                    })
+                );
+            }
+            Ok(encoding.unwrap_or(QualityEncoding::Phred33))
+        };
+        let r1 = detect(&self.r1_in)?;
+        let r2 = detect(&self.r2_in)?;
+        let umi = match &self.umis {
+            UmiInput::FastQ(paths) => detect(paths)?,
+            // These UMIs are cut from the reads after their qualities have been converted.
+            UmiInput::Read(..) => target,
+            UmiInput::Table(_) | UmiInput::Index => QualityEncoding::Phred33,
+        };
+        Ok([r1, r2, umi].map(|from| QualityConversion::new(from, target)))
+    }
+
+    // The outputs are named after the first read files, so at least one must be given per read.
+    fn check_reads(&self) -> Result<()> {
+        if self.r1_in.is_empty() {
+            return Err(anyhow!(RuntimeErrors::NoInputFiles("read 1".to_string())));
+        }
+        if self.r2_in.is_empty() {
+            return Err(anyhow!(RuntimeErrors::NoInputFiles("read 2".to_string())));
+        }
+        Ok(())
+    }
+
+    pub fn run(self) -> Result<TransferSummary> {
+        // Check the inline placement and the header template before any files are opened, so that
+        // mistakes are reported immediately.
+        self.inline_layout()?;
+        let template = self.template()?;
+
+        if let UmiInput::FastQ(ru_in) = &self.umis {
+            if ru_in.is_empty() {
+                return Err(anyhow!(RuntimeErrors::NoInputFiles("the UMIs".to_string())));
+            }
+        }
+        self.check_reads()?;
+
+        match &self.umis {
+            // Every read file must be paired with a UMI file, e.g. lane by lane.
+            UmiInput::FastQ(ru_in)
+                if self.r1_in.len() != ru_in.len() || self.r2_in.len() != ru_in.len() =>
+            {
+                return Err(anyhow!(RuntimeErrors::InputCountMismatch(
+                    self.r1_in.len(),
+                    self.r2_in.len(),
+                    ru_in.len()
+                )));
+            }
+            _ if self.r1_in.len() != self.r2_in.len() => {
+                return Err(anyhow!(RuntimeErrors::ReadCountMismatch(
+                    self.r1_in.len(),
+                    self.r2_in.len()
+                )));
+            }
+            _ => {}
+        }
+
+        // Reads that already carry a UMI are recognized by the separator of the template.
+        let separator = template.umi_separator().unwrap_or_default();
+        let mut source = self.umis.open(separator, self.strict)?;
+        self.run_with(source.as_mut())
+    }
+
+    // Transfers the UMIs of any source, which replaces the configured `UmiInput`.
+    pub fn run_with(self, source: &mut dyn UmiSource) -> Result<TransferSummary> {
+        let inline_layout = self.inline_layout()?;
+        let template = self.template()?;
+        self.check_reads()?;
+
+        // Qualities of reads and UMIs are converted to the same encoding, so that they are not mixed
+        // within a record or between the outputs. The encodings are detected before any output is
+        // opened or moved away, so that undetectable inputs leave existing outputs untouched.
+        let [convert_r1, convert_r2, convert_umi] = match self.convert_quals_to {
+            Some(target) => self.quality_conversions(target)?,
+            None => [None; 3],
+        };
+
+        // Set the number of threads to max, unless manually specified. In case of failure, use only 1.
+        let num_threads = self.threads.unwrap_or_else(threads_available);
+
+        // Determine the number of threads available for output file compression.
+        let num_outputs = if self.ru_out.is_some() { 3 } else { 2 };
+        let threads_per_task = threads_per_task(num_threads, num_outputs);
+
+        // Read FastQ records from input files
+        let r1 = file_io::read_fastqs(&self.r1_in, self.strict).with_context(|| {
+            format!(
+                "Failed to read records from {}",
+                file_io::display_paths(&self.r1_in)
+            )
+        })?;
+        let r2 = file_io::read_fastqs(&self.r2_in, self.strict).with_context(|| {
+            format!(
+                "Failed to read records from {}",
+                file_io::display_paths(&self.r2_in)
+            )
+        })?;
+
+        // If output paths have been specified, check if the are ok to use or use prefix constructors.
+        let output1 = self.r1_out.clone().unwrap_or_else(|| {
+            file_io::append_umi_to_path(&self.r1_in[0], &self.naming, "R1", self.sample.as_deref())
+        });
+        let output2 = self.r2_out.clone().unwrap_or_else(|| {
+            file_io::append_umi_to_path(&self.r2_in[0], &self.naming, "R2", self.sample.as_deref())
+        });
+
+        // set the correct extension.
+        let mut output_paths = vec![
+            file_io::rectify_extension(output1, &self.gzip)?,
+            file_io::rectify_extension(output2, &self.gzip)?,
+        ];
+        if let Some(output) = self.ru_out.clone() {
+            output_paths.push(file_io::rectify_extension(output, &self.gzip)?);
+        }
+
+        // Outputs must neither replace an input nor each other. This is checked before existing
+        // outputs are moved away or overwritten.
+        let inputs = [
+            self.r1_in.as_slice(),
+            self.r2_in.as_slice(),
+            &self.umis.paths(),
+        ]
+        .concat();
+        file_io::check_collisions(&inputs, &output_paths)?;
+
+        // The progress of the run is saved next to the first output.
+        let sidecar = checkpoint::sidecar_path(&output_paths[0]);
+
+        // When resuming, the outputs of the interrupted run are continued instead of being overwritten.
+        // A resumed run continues to save its progress, at the interval of the interrupted run unless
+        // another one is given.
+        let (skip_records, lengths, checkpoint_every) = if self.resume {
+            let checkpoint = Checkpoint::load(&sidecar)?;
+            let lengths = checkpoint.lengths_for(&sidecar, &output_paths)?;
+            if self.verbose {
+                println!("Resuming after {} records.", checkpoint.records);
+            }
+            (
+                checkpoint.records,
+                Some(lengths),
+                self.checkpoint.or(checkpoint.every),
+            )
+        } else {
+            // modify if output path according to compression settings and check if exists.
+            output_paths = output_paths
+                .into_iter()
+                .map(|output| file_io::check_outputpath(output, &self.overwrite))
+                .collect::<Result<Vec<PathBuf>>>()?;
+            (0, None, self.checkpoint)
+        };
+
+        if self.verbose {
+            println!(
+                "Output 1 will be saved to: {}",
+                output_paths[0].to_string_lossy()
+            );
+            println!(
+                "Output 2 will be saved to: {}",
+                output_paths[1].to_string_lossy()
+            );
+            if let Some(output) = output_paths.get(2) {
+                println!("UMIs will be saved to: {}", output.to_string_lossy());
+            }
+        }
+
+        // The outputs are written to temporary files until all records have been processed. A run with
+        // checkpoints keeps them on failure, so that it can be resumed. A resumed run always keeps
+        // them, as they contain the progress that the sidecar refers to.
+        let outputs = file_io::AtomicOutputs::new(
+            &output_paths,
+            self.keep_partial || self.resume || checkpoint_every.is_some(),
+        );
+
+        let open_output = |index: usize| match &lengths {
+            Some(lengths) => file_io::resume_writer(
+                outputs.write_path(index).clone(),
+                lengths[index],
+                &self.gzip,
+                &threads_per_task,
+                &self.compression_level,
+                None,
+            ),
+            None => file_io::create_writer(
+                outputs.write_path(index).clone(),
+                &self.gzip,
+                &threads_per_task,
+                &self.compression_level,
+                None,
+            ),
+        };
+        let mut write_output_r1 = open_output(0)?;
+        let mut write_output_r2 = open_output(1)?;
+        // The UMI output is optional and written in sync with the read outputs.
+        let mut write_output_ru = if output_paths.len() > 2 {
+            Some(open_output(2)?)
+        } else {
+            None
+        };
+
+        // Saves the progress after all records up to now have been written completely.
+        let save_checkpoint = |records: u64,
+                               write_output_r1: &mut Box<dyn RecordSink>,
+                               write_output_r2: &mut Box<dyn RecordSink>,
+                               write_output_ru: Option<&mut Box<dyn RecordSink>>|
+         -> Result<()> {
+            let mut lengths = vec![write_output_r1.checkpoint()?, write_output_r2.checkpoint()?];
+            if let Some(write_output_ru) = write_output_ru {
+                lengths.push(write_output_ru.checkpoint()?);
+            }
+            Checkpoint::new(records, checkpoint_every, &output_paths, lengths).save(&sidecar)
+        };
+        if checkpoint_every.is_some() && !self.resume {
+            save_checkpoint(
+                0,
+                &mut write_output_r1,
+                &mut write_output_r2,
+                write_output_ru.as_mut(),
+            )?;
+        }
+
+        // Record counter
+        let mut counter: u64 = skip_records;
+
+        if self.verbose {
+            println!("Transferring UMIs to records...");
+        }
+
+        // Iterate over records in input files
+        // Records that were already written before the last checkpoint are skipped.
+        let skip = skip_records as usize;
+        let (mut r1, mut r2) = (r1.skip(skip), r2.skip(skip));
+        source.skip(skip)?;
+        // Unlike izip!(), advance both inputs manually, so that an input that ends early is detected.
+        loop {
+            let (mut r1_rec, mut r2_rec) = match (r1.next(), r2.next()) {
+                (None, None) => break,
+                (Some(r1_rec), Some(r2_rec)) => (r1_rec?, r2_rec?),
+                (r1_rec, _) => {
+                    let ended = if r1_rec.is_none() {
+                        &self.r1_in
+                    } else {
+                        &self.r2_in
+                    };
+                    return Err(anyhow!(RuntimeErrors::TruncatedInput(
+                        file_io::display_paths(ended),
+                        counter as usize
+                    )));
+                }
+            };
+
+            if let Some(conversion) = convert_r1 {
+                r1_rec = conversion.convert_record(&r1_rec);
+            }
+            if let Some(conversion) = convert_r2 {
+                r2_rec = conversion.convert_record(&r2_rec);
+            }
+
+            // Both reads must belong to the same pair, whichever source provides the UMIs.
+            if pair_name(r1_rec.id()) != pair_name(r2_rec.id()) {
+                return Err(anyhow!(RuntimeErrors::ReadIDMismatch));
+            }
+
+            // The source checks that the UMI belongs to the reads and may trim it from them.
+            let mut umi = source.next_umi(&mut r1_rec, &mut r2_rec)?;
+            if let Some(conversion) = convert_umi {
+                umi.qual = conversion.convert(&umi.qual);
+            }
+
+            // Step counter
+            counter += 1;
+
+            // Write to Output file
+            let r1_rec = match self.destination {
+                UMIDestination::Header => umi_to_record_header(
+                    r1_rec,
+                    &umi.seq,
+                    &umi.qual,
+                    &template,
+                    1,
+                    self.edit_nr,
+                    &self.on_existing_umi,
+                ),
+                UMIDestination::Inline if inline_layout.includes(1) => {
+                    let read_nr = if self.edit_nr { Some(1) } else { None };
+                    umi_to_record_seq(r1_rec, &umi.seq, &umi.qual, &inline_layout, read_nr)
+                }
+                UMIDestination::Inline => correct_read_nr(r1_rec, self.edit_nr.then_some(1)),
+            }?;
+
+            // Name the UMI record like the read, so that tools can match them by name.
+            if let Some(write_output_ru) = write_output_ru.as_mut() {
+                let umi_rec = bio::io::fastq::Record::with_attrs(
+                    r1_rec.id(),
+                    umi.desc.as_deref(),
+                    &umi.seq,
+                    &umi.qual,
+                );
+                write_output_ru.write_record(&umi_rec)?;
+            }
+
+            write_output_r1.write_record(&r1_rec)?;
+
+            let r2_rec = match self.destination {
+                UMIDestination::Header => umi_to_record_header(
+                    r2_rec,
+                    &umi.seq,
+                    &umi.qual,
+                    &template,
+                    2,
+                    self.edit_nr,
+                    &self.on_existing_umi,
+                ),
+                UMIDestination::Inline if inline_layout.includes(2) => {
+                    let read_nr = if self.edit_nr { Some(2) } else { None };
+                    umi_to_record_seq(r2_rec, &umi.seq, &umi.qual, &inline_layout, read_nr)
+                }
+                UMIDestination::Inline => correct_read_nr(r2_rec, self.edit_nr.then_some(2)),
+            }?;
+
+            write_output_r2.write_record(&r2_rec)?;
+
+            if checkpoint_every.is_some_and(|every| counter.is_multiple_of(every)) {
+                save_checkpoint(
+                    counter,
+                    &mut write_output_r1,
+                    &mut write_output_r2,
+                    write_output_ru.as_mut(),
+                )?;
+            }
+            if let Some(progress) = &self.progress {
+                if counter.is_multiple_of(progress.every) {
+                    (progress.callback)(counter)?;
+                }
+            }
+        }
+
+        // UMIs left over mean that both read files ended early.
+        if source.remaining() {
+            return Err(anyhow!(RuntimeErrors::ReadsEndedEarly(
+                format!(
+                    "{} and {}",
+                    file_io::display_paths(&self.r1_in),
+                    file_io::display_paths(&self.r2_in)
+                ),
+                counter as usize
+            )));
+        }
+
+        // All records have been written, so the outputs can be moved to their final paths.
+        // The writers are finished explicitly, so that a failure to complete an output is not lost.
+        // All writers are finished before the first error is reported.
+        let writers = [
+            Some(write_output_r1),
+            Some(write_output_r2),
+            write_output_ru,
+        ];
+        let [output1_bytes, output2_bytes, output_umi_bytes] = writers
+            .each_ref()
+            .map(|writer| writer.as_ref().map(|writer| writer.bytes_written()));
+        let results: Vec<std::io::Result<()>> = writers
+            .into_iter()
+            .flatten()
+            .map(RecordSink::finish)
+            .collect();
+        for (index, result) in results.into_iter().enumerate() {
+            result.map_err(|source| {
+                anyhow!(RuntimeErrors::OutputIncomplete {
+                    path: output_paths[index].clone(),
+                    source
+                })
+            })?;
+        }
+        outputs.commit()?;
+
+        // The sidecar is only removed once the outputs are complete.
+        if sidecar.exists() {
+            fs::remove_file(&sidecar)?;
+        }
+
+        let mut output_paths = output_paths.into_iter();
+        Ok(TransferSummary {
+            records: counter,
+            resumed_after: skip_records,
+            output1: output_paths.next().unwrap_or_default(),
+            output2: output_paths.next().unwrap_or_default(),
+            output_umi: output_paths.next(),
+            output1_bytes: output1_bytes.unwrap_or_default(),
+            output2_bytes: output2_bytes.unwrap_or_default(),
+            output_umi_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn test_transfer_defaults() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let summary = UmiTransfer::new(
+            "tests/seqdata/read1.fq",
+            "tests/seqdata/read2.fq",
+            "tests/seqdata/umi.fq",
+        )
+        .out(temp_dir.path().join("read1_out.fq"))
+        .out2(temp_dir.path().join("read2_out.fq"))
+        .threads(1)
+        .run()
+        .unwrap();
+
+        assert_eq!(summary.records, 10);
+        assert_eq!(summary.resumed_after, 0);
+        assert_eq!(summary.output1, temp_dir.path().join("read1_out.fq"));
+        assert_eq!(summary.output_umi, None);
+        assert_eq!(summary.output_umi_bytes, None);
+        let written = fs::read_to_string(&summary.output1).unwrap();
+        assert_eq!(summary.output1_bytes, written.len() as u64);
+        assert_eq!(
+            summary.output2_bytes,
+            fs::metadata(&summary.output2).unwrap().len()
+        );
+        assert!(written.starts_with("@SCILIFELAB:500:NGISTLM:1:1101:19994:1016:CCTGAGACC "));
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_transfer_reports_progress() {
+        let temp_dir = TempDir::new().expect("Failed to create temporary directory");
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports = Arc::clone(&reported);
+        UmiTransfer::new(
+            "tests/seqdata/read1.fq",
+            "tests/seqdata/read2.fq",
+            "tests/seqdata/umi.fq",
+        )
+        .out(temp_dir.path().join("read1_out.fq"))
+        .out2(temp_dir.path().join("read2_out.fq"))
+        .threads(1)
+        .progress(4, move |records| {
+            reports.lock().unwrap().push(records);
+            Ok(())
+        })
+        .run()
+        .unwrap();
+        assert_eq!(*reported.lock().unwrap(), vec![4, 8]);
+
+        // The run stops at the first failed callback and keeps no outputs.
+        let error = UmiTransfer::new(
+            "tests/seqdata/read1.fq",
+            "tests/seqdata/read2.fq",
+            "tests/seqdata/umi.fq",
+        )
+        .out(temp_dir.path().join("read1_cancelled.fq"))
+        .out2(temp_dir.path().join("read2_cancelled.fq"))
+        .threads(1)
+        .progress(4, |_| Err(anyhow!("cancelled")))
+        .run()
+        .unwrap_err();
+        assert_eq!(error.to_string(), "cancelled");
+        assert!(!temp_dir.path().join("read1_cancelled.fq").exists());
+
+        temp_dir
+            .close()
+            .expect("Failed to remove temporary directory");
+    }
+
+    #[test]
+    fn test_transfer_validates_settings_first() {
+        let result = UmiTransfer::new("missing_r1.fq", "missing_r2.fq", "missing_umi.fq")
+            .destination(UMIDestination::Inline)
+            .spacer("XY")
+            .run();
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid inline spacer"));
+
+        let result = UmiTransfer::from_lanes(
+            vec![PathBuf::from("r1_l1.fq"), PathBuf::from("r1_l2.fq")],
+            vec![PathBuf::from("r2_l1.fq")],
+            vec![PathBuf::from("umi_l1.fq")],
+        )
+        .run();
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Received 2 file(s) for read 1"));
+    }
+}
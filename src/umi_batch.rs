@@ -0,0 +1,474 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::config;
+use crate::illumina;
+use crate::umi_external::{self, NamingOptions, OptsExternal, TransferOptions};
+use umi_transfer::auxiliary::{threads_available, threads_per_task};
+use umi_transfer::file_io::{self, OutputNaming, OverwritePolicy};
+use umi_transfer::header_template::{HeaderTemplate, DEFAULT_TEMPLATE};
+use umi_transfer::umi_errors::RuntimeErrors;
+
+#[derive(Debug, Parser)]
+#[clap(group(clap::ArgGroup::new("samples").required(true).args(["sheet", "dir"])))]
+pub struct OptsBatch {
+    #[clap(flatten)]
+    settings: TransferOptions,
+    #[clap(flatten)]
+    naming: NamingOptions,
+    #[clap(
+        short = 'j',
+        long = "jobs",
+        help = "Number of samples to process concurrently. The threads given with --threads are split evenly between them. Defaults to 1.
+        \n "
+    )]
+    jobs: Option<usize>,
+    #[clap(
+        long = "umi-read",
+        value_parser = illumina::parse_read,
+        help = "Read that contains the UMIs when samples are discovered with --dir, e.g. 'R2' or 'I1'. Defaults to 'R2'.
+        \n "
+    )]
+    umi_read: Option<String>,
+    #[clap(
+        long = "merge-lanes",
+        help = "Merge all lanes of a sample into one pair of output files when samples are discovered with --dir.
+        \n "
+    )]
+    merge_lanes: bool,
+    #[clap(
+        long = "include-undetermined",
+        help = "Also process the Undetermined reads when samples are discovered with --dir.
+        \n "
+    )]
+    undetermined: bool,
+    #[clap(
+        short = 's',
+        long = "sheet",
+        help = "Sample sheet in CSV or TSV format with the columns: sample, R1, R2, UMI and an optional output prefix.
+        \n"
+    )]
+    sheet: Option<PathBuf>,
+    #[clap(
+        long = "dir",
+        help = "Directory with FastQ files named by bcl2fastq or BCL Convert. Files are grouped by sample and lane.
+        \n"
+    )]
+    dir: Option<PathBuf>,
+}
+
+// One row of the sample sheet. Each input may consist of several files, e.g. lanes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub r1_in: Vec<PathBuf>,
+    pub r2_in: Vec<PathBuf>,
+    pub ru_in: Vec<PathBuf>,
+    pub out_prefix: Option<PathBuf>,
+}
+
+impl Sample {
+    // Output paths are derived from the input file names, or from the prefix if one was given. A
+    // prefix takes the place of the inputs, e.g. `out/A_R1.fastq` for the prefix `out/A`, so that
+    // the output directory, suffix and name template apply to all samples alike.
+    fn output_paths(&self, naming: &OutputNaming) -> (PathBuf, PathBuf) {
+        let output = |input: &Path, read: &str| {
+            let input = match &self.out_prefix {
+                Some(prefix) => PathBuf::from(format!("{}_{read}.fastq", prefix.to_string_lossy())),
+                None => input.to_path_buf(),
+            };
+            file_io::append_umi_to_path(&input, naming, read, Some(&self.name))
+        };
+        (output(&self.r1_in[0], "R1"), output(&self.r2_in[0], "R2"))
+    }
+}
+
+// Parses a sample sheet. The delimiter (tab or comma) is inferred from the first row,
+// a header row starting with "sample" as well as empty lines and # comments are skipped.
+// Several files per input are separated by semicolons.
+pub fn parse_sample_sheet(content: &str) -> Result<Vec<Sample>> {
+    let mut rows = content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .peekable();
+
+    let delimiter = match rows.peek() {
+        Some((_, line)) if line.contains('\t') => '\t',
+        _ => ',',
+    };
+
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+
+    for (row, (line_nr, line)) in rows.enumerate() {
+        let fields: Vec<&str> = line.split(delimiter).map(|field| field.trim()).collect();
+        if row == 0 && fields[0].eq_ignore_ascii_case("sample") {
+            continue;
+        }
+
+        if !(4..=5).contains(&fields.len()) {
+            return Err(anyhow!(RuntimeErrors::SampleSheetInvalid(
+                line_nr,
+                format!("expected 4 or 5 columns, but found {}", fields.len())
+            )));
+        }
+        // A file column that consists of separators only, e.g. `;`, lists no files either.
+        let files: Vec<Vec<PathBuf>> = fields[1..4]
+            .iter()
+            .map(|field| split_files(field))
+            .collect();
+        let empty_column = if fields[0].is_empty() {
+            Some(0)
+        } else {
+            files
+                .iter()
+                .position(Vec::is_empty)
+                .map(|column| column + 1)
+        };
+        if let Some(column) = empty_column {
+            return Err(anyhow!(RuntimeErrors::SampleSheetInvalid(
+                line_nr,
+                format!("column {} must not be empty", column + 1)
+            )));
+        }
+        if !names.insert(fields[0].to_string()) {
+            return Err(anyhow!(RuntimeErrors::SampleSheetInvalid(
+                line_nr,
+                format!("sample {} is listed more than once", fields[0])
+            )));
+        }
+
+        samples.push(Sample {
+            name: fields[0].to_string(),
+            r1_in: files[0].clone(),
+            r2_in: files[1].clone(),
+            ru_in: files[2].clone(),
+            out_prefix: fields
+                .get(4)
+                .filter(|prefix| !prefix.is_empty())
+                .map(PathBuf::from),
+        });
+    }
+    Ok(samples)
+}
+
+fn split_files(field: &str) -> Vec<PathBuf> {
+    field
+        .split(';')
+        .map(str::trim)
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub fn read_sample_sheet(path: &Path) -> Result<Vec<Sample>> {
+    let content = std::fs::read_to_string(path).map_err(|source| {
+        anyhow!(RuntimeErrors::FileNotFound {
+            path: path.into(),
+            source
+        })
+    })?;
+    parse_sample_sheet(&content)
+}
+
+// Resolves the output paths of all samples before any processing starts. Overwrite prompts
+// are therefore shown one after another and not interleaved by concurrently running samples.
+// Samples that were found incomplete keep their error and fail without being processed.
+fn prepare_samples(
+    samples: Vec<(String, Result<Sample>)>,
+    settings: &TransferOptions,
+    naming: &OutputNaming,
+) -> Vec<(String, Result<OptsExternal>)> {
+    let mut outputs: HashSet<PathBuf> = HashSet::new();
+
+    samples
+        .into_iter()
+        .map(|(name, sample)| {
+            let sample = match sample {
+                Ok(sample) => sample,
+                Err(err) => return (name, Err(err)),
+            };
+            let (output1, output2) = sample.output_paths(naming);
+            let prepared = [output1, output2]
+                .map(|output| {
+                    let output = file_io::rectify_extension(output, &settings.gzip())?;
+                    if !outputs.insert(output.clone()) {
+                        return Err(anyhow!(
+                            "Output file {} is used by more than one sample.",
+                            output.display()
+                        ));
+                    }
+                    file_io::check_outputpath(output, &settings.overwrite_policy())
+                })
+                .into_iter()
+                .collect::<Result<Vec<PathBuf>>>()
+                .map(|mut outputs| {
+                    let r2_out = outputs.pop();
+                    let r1_out = outputs.pop();
+                    OptsExternal {
+                        // Overwriting has already been confirmed, so run() must not prompt again.
+                        settings: TransferOptions {
+                            overwrite: Some(OverwritePolicy::Always),
+                            ..settings.clone()
+                        },
+                        r1_in: sample.r1_in,
+                        r2_in: sample.r2_in,
+                        ru_in: sample.ru_in,
+                        naming: NamingOptions::default(),
+                        umi_table: None,
+                        umi_in_read: None,
+                        umi_from_index: false,
+                        r1_out,
+                        r2_out,
+                        ru_out: None,
+                        sample: Some(sample.name.clone()),
+                        checkpoint: None,
+                        resume: false,
+                        keep_partial: false,
+                    }
+                });
+            (sample.name, prepared)
+        })
+        .collect()
+}
+
+pub fn run(args: OptsBatch) -> Result<u64> {
+    let samples = match (&args.sheet, &args.dir) {
+        (Some(sheet), _) => read_sample_sheet(sheet)
+            .with_context(|| {
+                format!(
+                    "Failed to read the sample sheet {}",
+                    &sheet.to_string_lossy()
+                )
+            })?
+            .into_iter()
+            .map(|sample| (sample.name.clone(), Ok(sample)))
+            .collect(),
+        (None, Some(dir)) => {
+            let umi_read = args.umi_read.as_deref().unwrap_or("R2");
+            illumina::discover_samples(dir, umi_read, args.merge_lanes, args.undetermined)
+                .with_context(|| {
+                    format!("Failed to discover samples in {}", &dir.to_string_lossy())
+                })?
+        }
+        // Prevented by the argument group.
+        (None, None) => unreachable!(),
+    };
+
+    // The settings from the configuration file are needed before the samples are processed.
+    let settings = config::apply_config(args.settings)?;
+
+    // Every sample has a name, so the header template can be validated once for all of them.
+    HeaderTemplate::new(
+        settings
+            .header_template
+            .as_deref()
+            .unwrap_or(DEFAULT_TEMPLATE),
+        settings.delim.as_deref().unwrap_or(":"),
+        Some(""),
+    )?;
+
+    let naming = args.naming.naming()?;
+
+    let num_samples = samples.len();
+    let jobs = args.jobs.unwrap_or(1).clamp(1, num_samples.max(1));
+
+    // Split the overall thread budget between the concurrently processed samples.
+    let num_threads = settings.num_threads.unwrap_or_else(threads_available);
+    let threads_per_sample = threads_per_task(num_threads, jobs);
+
+    println!(
+        "Processing {} samples, {} at a time with {} threads each.",
+        num_samples, jobs, threads_per_sample
+    );
+
+    let settings = TransferOptions {
+        num_threads: Some(threads_per_sample),
+        ..settings
+    };
+
+    // Samples, whose outputs can not be used, fail already during preparation.
+    let mut results: Vec<(String, Result<u64>)> = Vec::with_capacity(num_samples);
+    let mut queue: Vec<(usize, String, OptsExternal)> = Vec::with_capacity(num_samples);
+    for (index, (name, prepared)) in prepare_samples(samples, &settings, &naming)
+        .into_iter()
+        .enumerate()
+    {
+        match prepared {
+            Ok(opts) => {
+                queue.push((index, name.clone(), opts));
+                results.push((name, Ok(0)));
+            }
+            Err(err) => results.push((name, Err(err))),
+        }
+    }
+
+    // Each worker picks the next sample from the shared queue until it is empty.
+    let queue = Mutex::new(queue.into_iter());
+    let finished: Mutex<Vec<(usize, Result<u64>)>> = Mutex::new(Vec::with_capacity(num_samples));
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().next();
+                let Some((index, name, opts)) = next else {
+                    break;
+                };
+                let result = umi_external::run_merged(opts)
+                    .map(|summary| summary.records)
+                    .with_context(|| format!("Failed to include the UMIs for sample {}", name));
+                finished.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    for (index, result) in finished.into_inner().unwrap() {
+        results[index].1 = result;
+    }
+
+    println!("\nSummary of {} samples:", num_samples);
+    let mut total_records = 0;
+    let mut failures = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(counter) => {
+                total_records += counter;
+                println!("  {}: processed {} records", name, counter);
+            }
+            Err(err) => {
+                failures += 1;
+                eprintln!("  {}: FAILED - {:#}", name, err);
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!(RuntimeErrors::BatchFailed(failures, num_samples)));
+    }
+    Ok(total_records)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_sample_sheet_csv_with_header() {
+        let sheet = "sample,R1,R2,UMI,prefix\n\
+                     A,a_R1.fq,a_R3.fq,a_R2.fq,out/A\n\
+                     B,b_R1.fq,b_R3.fq,b_R2.fq,\n";
+        let samples = parse_sample_sheet(sheet).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(
+            samples[0],
+            Sample {
+                name: "A".to_string(),
+                r1_in: vec![PathBuf::from("a_R1.fq")],
+                r2_in: vec![PathBuf::from("a_R3.fq")],
+                ru_in: vec![PathBuf::from("a_R2.fq")],
+                out_prefix: Some(PathBuf::from("out/A")),
+            }
+        );
+        assert_eq!(samples[1].out_prefix, None);
+        assert_eq!(
+            samples[1].output_paths(&OutputNaming::default()),
+            (
+                PathBuf::from("b_R1_with_UMIs.fq"),
+                PathBuf::from("b_R3_with_UMIs.fq")
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_tsv_with_comments() {
+        let sheet = "# flowcell 1\n\
+                     A\ta_R1.fq\ta_R3.fq\ta_R2.fq\tout/A\n\
+                     \n\
+                     B\tb_L1_R1.fq;b_L2_R1.fq\tb_L1_R3.fq;b_L2_R3.fq\tb_L1_R2.fq;b_L2_R2.fq\n";
+        let samples = parse_sample_sheet(sheet).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].name, "B");
+        assert_eq!(
+            samples[1].ru_in,
+            vec![PathBuf::from("b_L1_R2.fq"), PathBuf::from("b_L2_R2.fq")]
+        );
+        assert_eq!(
+            samples[0].output_paths(&OutputNaming::default()),
+            (
+                PathBuf::from("out/A_R1_with_UMIs.fastq"),
+                PathBuf::from("out/A_R2_with_UMIs.fastq")
+            )
+        );
+    }
+
+    #[test]
+    fn test_output_paths_follow_naming_with_and_without_prefix() {
+        let sheet =
+            "A,a_R1.fq.gz,a_R3.fq.gz,a_R2.fq.gz,out/A\nB,b_R1.fq.gz,b_R3.fq.gz,b_R2.fq.gz\n";
+        let samples = parse_sample_sheet(sheet).unwrap();
+        let naming = OutputNaming::new(
+            Some(PathBuf::from("results")),
+            None,
+            Some("{sample}.{read}.fastq.gz"),
+        )
+        .unwrap();
+        for sample in &samples {
+            assert_eq!(
+                sample.output_paths(&naming),
+                (
+                    PathBuf::from(format!("results/{}.R1.fastq.gz", sample.name)),
+                    PathBuf::from(format!("results/{}.R2.fastq.gz", sample.name))
+                )
+            );
+        }
+
+        let naming = OutputNaming::new(None, Some(".umi"), None).unwrap();
+        assert_eq!(
+            samples[0].output_paths(&naming).0,
+            PathBuf::from("out/A_R1.umi.fastq")
+        );
+        assert_eq!(
+            samples[1].output_paths(&naming).0,
+            PathBuf::from("b_R1.umi.fq.gz")
+        );
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_rejects_invalid_rows() {
+        let result = parse_sample_sheet("A,a_R1.fq,a_R3.fq\n");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Line 1 of the sample sheet is invalid: expected 4 or 5 columns"));
+
+        let result = parse_sample_sheet("A,a_R1.fq,,a_R2.fq\n");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("column 3 must not be empty"));
+
+        let result = parse_sample_sheet("A,a_R1.fq,a_R3.fq,a_R2.fq\nB,b_R1.fq,  ;  ,b_R2.fq\n");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Line 2 of the sample sheet is invalid: column 3 must not be empty"));
+
+        let result = parse_sample_sheet("A\t;\ta_R3.fq\ta_R2.fq\n");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Line 1 of the sample sheet is invalid: column 2 must not be empty"));
+
+        let result = parse_sample_sheet("A,a_R1.fq,a_R3.fq,a_R2.fq\nA,b_R1.fq,b_R3.fq,b_R2.fq\n");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Line 2 of the sample sheet is invalid: sample A is listed more than once"));
+    }
+}
@@ -1,46 +1,207 @@
+use std::io;
 use std::path::PathBuf;
+use thiserror::Error;
 
-#[derive(Debug)]
+// Errors that occur while processing the input files. Errors that are caused by a failed I/O
+// operation keep it as source, so that it is shown as cause when the error is reported.
+#[derive(Debug, Error)]
 pub enum RuntimeErrors {
-    FileExists(Option<PathBuf>),
-    FileNotFound(Option<PathBuf>),
-    OutputNotWriteable(Option<PathBuf>),
+    #[error(
+        "Output file {} exists, but must not be overwritten. Use --overwrite always or --overwrite backup to replace it.",
+        .0.display()
+    )]
+    FileExists(PathBuf),
+    #[error("{} does not exist or is not readable!", .path.display())]
+    FileNotFound {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Output file {} is missing or not writeable.", .path.display())]
+    OutputNotWriteable {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Output file {} could not be completed.", .path.display())]
+    OutputIncomplete {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Input file {} could not be read.", .path.display())]
+    InputNotReadable {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Record {record} of {} is not valid FastQ.", .path.display())]
+    InvalidFastQ {
+        path: PathBuf,
+        record: usize,
+        #[source]
+        source: bio::io::fastq::Error,
+    },
+    #[error("Record {record} of {} is malformed: {reason}.", .path.display())]
+    MalformedRecord {
+        path: PathBuf,
+        record: usize,
+        reason: String,
+    },
+    #[error("{0} ended unexpectedly after {1} records.")]
+    TruncatedInput(String, usize),
+    #[error("{0} ended after {1} records, while the UMI input has more records.")]
+    ReadsEndedEarly(String, usize),
+    #[error("IDs of UMI and read records mismatch. Please provide sorted files as input!")]
     ReadIDMismatch,
-    ReadWriteError(bio::io::fastq::Record),
+    #[error("Failure to write read {id} to file.")]
+    ReadWriteError {
+        id: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Validation of the input files found {0} problem(s).")]
+    ValidationFailed(usize),
+    #[error("Line {0} of the sample sheet is invalid: {1}.")]
+    SampleSheetInvalid(usize, String),
+    #[error("{0} of {1} samples could not be processed.")]
+    BatchFailed(usize, usize),
+    #[error("No FastQ files with Illumina file names were found in {}.", .0.display())]
+    NoSamplesFound(PathBuf),
+    #[error("Read group {0} is incomplete: {1}.")]
+    IncompleteReadGroup(String, String),
+    #[error("Received {0} file(s) for read 1, {1} for read 2 and {2} for the UMIs. Please provide the same number of files for each!")]
+    InputCountMismatch(usize, usize, usize),
+    #[error("Configuration file {path} is invalid: {1}", path = .0.display())]
+    ConfigInvalid(PathBuf, String),
+    #[error("The read number of header '{0}' could not be identified. Supported are Casava 1.8 comments (1:N:0:BARCODE), /1 suffixes, SRA-style comments (1/1) and headers without comment.")]
+    UnsupportedHeader(String),
+    #[error("Header template '{0}' is invalid: {1}.")]
+    HeaderTemplateInvalid(String, String),
+    #[error("Output name '{0}' is invalid: {1}.")]
+    NameTemplateInvalid(String, String),
+    #[error(
+        "Read {0} already contains a UMI. Use --on-existing-umi to replace, append or skip it."
+    )]
+    UMIExists(String),
+    #[error("Invalid inline spacer: {0}.")]
+    SpacerInvalid(String),
+    #[error("Checkpoint {path} can not be used: {1}.", path = .0.display())]
+    CheckpointInvalid(PathBuf, String),
+    #[error("Line {1} of UMI table {path} is invalid: {2}.", path = .0.display())]
+    UMITableInvalid(PathBuf, usize, String),
+    #[error("No UMI found for read {0}: {1}.")]
+    UMIUnavailable(String, String),
+    #[error("No input files were given for {0}.")]
+    NoInputFiles(String),
+    #[error("Received {0} file(s) for read 1 and {1} for read 2. Please provide the same number of files for each!")]
+    ReadCountMismatch(usize, usize),
+    #[error("Output file {} is the same file as the input {}. Please choose another output name.", .0.display(), .1.display())]
+    OutputIsInput(PathBuf, PathBuf),
+    #[error("Output files {} and {} are the same file. Please choose different names for the outputs.", .0.display(), .1.display())]
+    OutputCollision(PathBuf, PathBuf),
 }
 
-impl std::fmt::Display for RuntimeErrors {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+// Exit status of a run that failed for a reason other than the ones below, e.g. a failed batch.
+pub const EXIT_FAILURE: i32 = 1;
+// Exit status if the command line arguments are invalid.
+pub const EXIT_USAGE: i32 = 2;
+// Exit status if the reader of an output closed the pipe, like a process terminated by SIGPIPE.
+pub const EXIT_BROKEN_PIPE: i32 = 141;
+
+impl RuntimeErrors {
+    // Exit status of the command line interface. Failures to read or write files (3-6) may succeed
+    // if retried, while problems with the content of the inputs (7-9) will not. See the README.
+    pub fn exit_code(&self) -> i32 {
         match self {
-            Self::FileExists(None) => {
-                write!(f, "Output file exists, but must not be overwritten.")
-            }
-            Self::FileExists(Some(path)) => write!(
-                f,
-                "Output file {} exists, but must not be overwritten.",
-                path.display()
-            ),
-            Self::FileNotFound(None) => {
-                write!(f, "Specified file does not exist or is not readable!")
-            }
-            Self::FileNotFound(Some(path)) => {
-                write!(f, "{} does not exist or is not readable!", path.display())
-            }
-            Self::OutputNotWriteable(None) => {
-                write!(f, "Output file is missing or not writeable.")
-            }
-            Self::OutputNotWriteable(Some(path)) => write!(
-                f,
-                "Output file {} is missing or not writeable.",
-                path.display()
-            ),
-            Self::ReadIDMismatch => write!(
-                f,
-                "IDs of UMI and read records mismatch. Please provide sorted files as input!"
-            ),
-            Self::ReadWriteError(record) => {
-                write!(f, "Failure to write read {} to file.", record.id())
-            }
+            Self::FileNotFound { .. } => 3,
+            Self::FileExists(_) => 4,
+            Self::OutputNotWriteable { .. }
+            | Self::OutputIncomplete { .. }
+            | Self::ReadWriteError { .. } => 5,
+            Self::InputNotReadable { .. } => 6,
+            Self::ReadIDMismatch => 7,
+            Self::TruncatedInput(..) | Self::ReadsEndedEarly(..) => 8,
+            Self::InvalidFastQ { .. } | Self::MalformedRecord { .. } => 9,
+            _ => EXIT_FAILURE,
         }
     }
 }
+
+// Exit status for an error, determined by the outermost RuntimeError in its chain of causes.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if is_broken_pipe(error) {
+        return EXIT_BROKEN_PIPE;
+    }
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<RuntimeErrors>())
+        .map_or(EXIT_FAILURE, RuntimeErrors::exit_code)
+}
+
+// Checks whether an error was caused by a reader that closed its end of a pipe, e.g. a FIFO
+// output that is consumed by `head`. This is not a failure of umi-transfer itself.
+pub fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_source_is_reported() {
+        let error = anyhow!(RuntimeErrors::ReadWriteError {
+            id: "read1".to_string(),
+            source: io::Error::other("disk quota exceeded"),
+        });
+        let message = format!("{:?}", error);
+        assert!(message.contains("Failure to write read read1 to file."));
+        assert!(message.contains("Caused by:"));
+        assert!(message.contains("disk quota exceeded"));
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let error = anyhow!(RuntimeErrors::ReadWriteError {
+            id: "read1".to_string(),
+            source: io::Error::from(io::ErrorKind::BrokenPipe),
+        })
+        .context("Failed to include the UMIs");
+        assert!(is_broken_pipe(&error));
+
+        let error = anyhow!(RuntimeErrors::ReadWriteError {
+            id: "read1".to_string(),
+            source: io::Error::other("disk quota exceeded"),
+        });
+        assert!(!is_broken_pipe(&error));
+        assert!(!is_broken_pipe(&anyhow!(RuntimeErrors::ReadIDMismatch)));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let error = anyhow!(RuntimeErrors::ReadIDMismatch).context("Failed to include the UMIs");
+        assert_eq!(exit_code(&error), 7);
+
+        let error = anyhow!(RuntimeErrors::FileExists(PathBuf::from(
+            "read1_with_UMIs.fq"
+        )));
+        assert_eq!(exit_code(&error), 4);
+
+        let error = anyhow!(RuntimeErrors::BatchFailed(1, 2));
+        assert_eq!(exit_code(&error), EXIT_FAILURE);
+
+        let error = anyhow!(RuntimeErrors::OutputIncomplete {
+            path: PathBuf::from("read1_with_UMIs.fq"),
+            source: io::Error::from(io::ErrorKind::BrokenPipe),
+        });
+        assert_eq!(exit_code(&error), EXIT_BROKEN_PIPE);
+        assert_eq!(exit_code(&anyhow!("unexpected")), EXIT_FAILURE);
+    }
+}
@@ -1,51 +1,77 @@
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use itertools::izip;
+use anyhow::Result;
+use clap::builder::BoolishValueParser;
+use clap::{ArgGroup, Parser};
 use std::path::PathBuf;
 
-use super::file_io;
-use crate::auxiliary::{threads_available, threads_per_task};
-use crate::read_editing::{umi_to_record_header, umi_to_record_seq, UMIDestination};
-use crate::umi_errors::RuntimeErrors;
-#[derive(Debug, Parser)]
-pub struct OptsExternal {
+use crate::config;
+use umi_transfer::file_io::{OutputNaming, OverwritePolicy};
+use umi_transfer::header_template::DEFAULT_TEMPLATE;
+use umi_transfer::read_editing::{ExistingUMI, InlineEnd, InlineReads, UMIDestination};
+use umi_transfer::umi_source::{parse_umi_in_read, UmiInput, UmiRead};
+use umi_transfer::{QualityEncoding, TransferSummary, UmiTransfer};
+
+// Settings that determine how the UMIs are transferred, shared by all subcommands that transfer UMIs.
+// Besides the command line, each can be set with an environment variable or in a configuration file.
+#[derive(Debug, Clone, Parser)]
+pub struct TransferOptions {
+    #[clap(
+        long = "config",
+        env = "UMI_TRANSFER_CONFIG",
+        help = "Configuration file in TOML or YAML format with default values for the settings below. Command line arguments and environment variables take precedence.
+        \n "
+    )]
+    pub config: Option<PathBuf>,
     #[clap(
         short = 'p',
         long = "position",
+        env = "UMI_TRANSFER_POSITION",
         help = "Choose the target position for the UMI: 'header' or 'inline'. Defaults to 'header'.
-        \n ",
-        default_value = "header"
+        \n "
     )]
-    target_position: UMIDestination,
+    pub target_position: Option<UMIDestination>,
     #[clap(
         short = 'c',
         long = "correct_numbers",
-        help = "Read numbers will be altered to ensure the canonical read numbers 1 and 2 in output file sequence headers.
+        env = "UMI_TRANSFER_CORRECT_NUMBERS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL",
+        value_parser = BoolishValueParser::new(),
+        help = "Read numbers will be altered to ensure the canonical read numbers 1 and 2 in output file sequence headers. Use --correct_numbers=false to switch it off if it is enabled in the configuration file.
         \n "
     )]
-    edit_nr: bool,
+    pub edit_nr: Option<bool>,
 
     #[clap(
         short = 'z',
         long = "gzip",
-        help = "Compress output files. Turned off by default.
+        env = "UMI_TRANSFER_GZIP",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL",
+        value_parser = BoolishValueParser::new(),
+        help = "Compress output files. Turned off by default, or with --gzip=false.
         \n "
     )]
-    gzip: bool,
+    pub gzip: Option<bool>,
     #[clap(
         short = 'l',
         long = "compression_level",
+        env = "UMI_TRANSFER_COMPRESSION_LEVEL",
         help = "Choose the compression level: Maximum 9, defaults to 3. Higher numbers result in smaller files but take longer to compress.
         \n "
     )]
-    compression_level: Option<u32>,
+    pub compression_level: Option<u32>,
     #[clap(
         short = 't',
         long = "threads",
+        env = "UMI_TRANSFER_THREADS",
         help = "Maximum number of threads to use for processing. Preferably pick odd numbers, 9 or 11 recommended. Defaults to the maximum number of cores available.
         \n "
     )]
-    num_threads: Option<usize>,
+    pub num_threads: Option<usize>,
     //#[clap(
     //    short = 'p',
     //    long = "pin_threads",
@@ -56,176 +82,336 @@ pub struct OptsExternal {
     #[clap(
         short = 'f',
         long = "force",
-        help = "Overwrite existing output files without further warnings or prompts.
+        env = "UMI_TRANSFER_FORCE",
+        conflicts_with = "overwrite",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL",
+        value_parser = BoolishValueParser::new(),
+        help = "Overwrite existing output files without further warnings or prompts. Same as '--overwrite always'.
         \n "
     )]
-    force: bool,
+    pub force: Option<bool>,
+    #[clap(
+        long = "overwrite",
+        env = "UMI_TRANSFER_OVERWRITE",
+        help = "What to do if an output file exists: 'prompt', 'never', 'always' or 'backup', which moves the existing file to a '.bak' file. Defaults to 'prompt', which does not overwrite the file if no terminal is attached.
+        \n "
+    )]
+    pub overwrite: Option<OverwritePolicy>,
+    #[clap(
+        long = "strict",
+        env = "UMI_TRANSFER_STRICT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL",
+        value_parser = BoolishValueParser::new(),
+        help = "Check that every input record has a read name, as many quality scores as bases, only IUPAC nucleotide codes and printable quality characters. Malformed records are an error.
+        \n "
+    )]
+    pub strict: Option<bool>,
+    #[clap(
+        long = "convert-quals-to",
+        env = "UMI_TRANSFER_CONVERT_QUALS_TO",
+        help = "Convert the qualities of reads and UMIs to 'phred33' or 'phred64'. The encoding of every input is detected from its first records. By default, qualities are copied unchanged.
+        \n "
+    )]
+    pub convert_quals_to: Option<QualityEncoding>,
     #[clap(
         short = 'd',
         long = "delim",
+        env = "UMI_TRANSFER_DELIM",
         help = "Delimiter to use when joining the UMIs to the read name. Defaults to `:`.
         \n "
     )]
-    delim: Option<String>,
+    pub delim: Option<String>,
+    #[clap(
+        long = "header-template",
+        env = "UMI_TRANSFER_HEADER_TEMPLATE",
+        help = "Layout of the output read header with the placeholders {id}, {delim}, {umi}, {umi_qual}, {desc}, {read_nr}, {sample} and {index}. Only used with '--position header'. Defaults to '{id}{delim}{umi} {desc}'.
+        \n "
+    )]
+    pub header_template: Option<String>,
+    #[clap(
+        long = "on-existing-umi",
+        env = "UMI_TRANSFER_ON_EXISTING_UMI",
+        help = "What to do if a read name already ends with the delimiter and a UMI: 'error', 'replace', 'append' or 'skip'. Only used with '--position header'. Defaults to 'error'.
+        \n "
+    )]
+    pub on_existing_umi: Option<ExistingUMI>,
+    #[clap(
+        long = "inline-end",
+        env = "UMI_TRANSFER_INLINE_END",
+        help = "End of the read to which an inline UMI is added: '5p' or '3p'. Only used with '--position inline'. Defaults to '5p'.
+        \n "
+    )]
+    pub inline_end: Option<InlineEnd>,
+    #[clap(
+        long = "inline-reads",
+        env = "UMI_TRANSFER_INLINE_READS",
+        help = "Reads to which an inline UMI is added: 'r1', 'r2' or 'both'. Only used with '--position inline'. Defaults to 'both'.
+        \n "
+    )]
+    pub inline_reads: Option<InlineReads>,
+    #[clap(
+        long = "spacer",
+        env = "UMI_TRANSFER_SPACER",
+        help = "Fixed sequence to insert between an inline UMI and the read. Only used with '--position inline'.
+        \n "
+    )]
+    pub spacer: Option<String>,
+    #[clap(
+        long = "spacer-qual",
+        env = "UMI_TRANSFER_SPACER_QUAL",
+        help = "Quality character of the spacer bases in Phred+33. Defaults to 'I'.
+        \n "
+    )]
+    pub spacer_qual: Option<char>,
+}
+
+// Flags are optional, so that `--gzip=false` or `UMI_TRANSFER_GZIP=false` can switch off a flag
+// of the configuration file. Flags that are not given anywhere are off.
+impl TransferOptions {
+    pub fn edit_nr(&self) -> bool {
+        self.edit_nr.unwrap_or(false)
+    }
+
+    pub fn gzip(&self) -> bool {
+        self.gzip.unwrap_or(false)
+    }
+
+    pub fn force(&self) -> bool {
+        self.force.unwrap_or(false)
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict.unwrap_or(false)
+    }
+
+    // --overwrite takes precedence over --force, which can also be set in the configuration file.
+    pub fn overwrite_policy(&self) -> OverwritePolicy {
+        self.overwrite.unwrap_or(if self.force() {
+            OverwritePolicy::Always
+        } else {
+            OverwritePolicy::Prompt
+        })
+    }
+}
+
+// Naming of the outputs that are not given explicitly, shared by all subcommands that write outputs.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct NamingOptions {
+    #[clap(
+        long = "outdir",
+        help = "Directory for output files that are named after the inputs. Defaults to the directory of the inputs.
+        \n "
+    )]
+    pub outdir: Option<PathBuf>,
+    #[clap(
+        long = "suffix",
+        help = "Suffix to insert before the extension of output files that are named after the inputs. Defaults to '_with_UMIs'.
+        \n "
+    )]
+    pub suffix: Option<String>,
+    #[clap(
+        long = "name-template",
+        conflicts_with = "suffix",
+        help = "File name of output files with the placeholders {sample}, {read} and {stem}, e.g. '{sample}_{read}.umi.fastq.gz'. {read} is R1 or R2, {stem} the input file name up to the first dot. {sample} defaults to the stem.
+        \n "
+    )]
+    pub name_template: Option<String>,
+}
+
+impl NamingOptions {
+    pub fn naming(&self) -> Result<OutputNaming> {
+        OutputNaming::new(
+            self.outdir.clone(),
+            self.suffix.as_deref(),
+            self.name_template.as_deref(),
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+#[clap(group(
+    ArgGroup::new("umi_source")
+        .required(true)
+        .args(["ru_in", "umi_table", "umi_in_read", "umi_from_index"])
+))]
+pub struct OptsExternal {
+    #[clap(flatten)]
+    pub settings: TransferOptions,
     #[clap(
         long = "in",
         required = true,
-        help = "[REQUIRED] Input file 1 with reads.
+        num_args = 1..,
+        help = "[REQUIRED] Input file 1 with reads. Multiple files, e.g. lanes, are processed in the given order.
     \n "
     )]
-    r1_in: PathBuf,
+    pub r1_in: Vec<PathBuf>,
     #[clap(
         long = "in2",
         required = true,
-        help = "[REQUIRED] Input file 2 with reads.
+        num_args = 1..,
+        help = "[REQUIRED] Input file 2 with reads. Multiple files, e.g. lanes, are processed in the given order.
     \n "
     )]
-    r2_in: PathBuf,
+    pub r2_in: Vec<PathBuf>,
     #[clap(
         short = 'u',
         long = "umi",
-        required = true,
-        help = "[REQUIRED] Input file with UMI.
+        num_args = 1..,
+        help = "[REQUIRED] Input file with UMI. Multiple files, e.g. lanes, are processed in the given order. Alternatively, the UMIs can be taken from --umi-table, --umi-in-read or --umi-from-index.
         \n"
     )]
-    ru_in: PathBuf,
+    pub ru_in: Vec<PathBuf>,
+    #[clap(
+        long = "umi-table",
+        value_name = "TSV",
+        help = "Take the UMIs from a tab-separated table of read names, UMIs and optionally UMI qualities instead of a FastQ file.
+    \n "
+    )]
+    pub umi_table: Option<PathBuf>,
+    #[clap(
+        long = "umi-in-read",
+        value_name = "READ:LENGTH",
+        value_parser = parse_umi_in_read,
+        help = "Take the UMIs from the first bases of a read, e.g. 'r1:8', and trim them from it.
+    \n "
+    )]
+    pub umi_in_read: Option<(UmiRead, usize)>,
+    #[clap(
+        long = "umi-from-index",
+        help = "Take the UMIs from the index in the comment of the read headers. With dual indices, the second index is used.
+    \n "
+    )]
+    pub umi_from_index: bool,
+    #[clap(flatten)]
+    pub naming: NamingOptions,
     #[clap(
         long = "out",
         help = "Path to FastQ output file for R1.
     \n "
     )]
-    r1_out: Option<PathBuf>,
+    pub r1_out: Option<PathBuf>,
     #[clap(
         long = "out2",
         help = "Path to FastQ output file for R2.
     \n "
     )]
-    r2_out: Option<PathBuf>,
+    pub r2_out: Option<PathBuf>,
+    #[clap(
+        long = "out-umi",
+        help = "Path to an additional FastQ output file for the UMIs. Its records carry the same names as the reads in the other outputs.
+    \n "
+    )]
+    pub ru_out: Option<PathBuf>,
+    #[clap(
+        long = "sample",
+        help = "Sample name to insert for the {sample} placeholder of the header template.
+    \n "
+    )]
+    pub sample: Option<String>,
+    #[clap(
+        long = "checkpoint",
+        value_name = "RECORDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Save the progress every given number of records, so that an interrupted run can be continued with --resume. Compressed outputs are then written as a series of gzip members.
+    \n "
+    )]
+    pub checkpoint: Option<u64>,
+    #[clap(
+        long = "resume",
+        help = "Continue an interrupted run from its last checkpoint and append to the existing outputs.
+    \n "
+    )]
+    pub resume: bool,
+    #[clap(
+        long = "keep-partial",
+        help = "Keep the temporary output files if the processing fails. They are removed by default.
+    \n "
+    )]
+    pub keep_partial: bool,
 }
 
-pub fn run(args: OptsExternal) -> Result<i32> {
-    // Enables editing id in output file 2 if --edit-nr flag was included
-    let mut edit_nr = false;
-    if args.edit_nr {
-        edit_nr = true;
-    }
-
-    // Set the number of threads to max, unless manually specified. In case of failure, use only 1.
-    let num_threads = args.num_threads.unwrap_or_else(threads_available);
-
-    // Determine the number of threads available for output file compression.
-    let threads_per_task = threads_per_task(num_threads, 2);
-
-    // Read FastQ records from input files
-    let r1 = file_io::read_fastq(&args.r1_in)
-        .with_context(|| {
-            format!(
-                "Failed to read records from {}",
-                &args.r1_in.to_string_lossy()
-            )
-        })?
-        .records();
-    let r2 = file_io::read_fastq(&args.r2_in)
-        .with_context(|| {
-            format!(
-                "Failed to read records from {}",
-                &args.r2_in.to_string_lossy()
-            )
-        })?
-        .records();
-    let ru = file_io::read_fastq(&args.ru_in)
-        .with_context(|| {
-            format!(
-                "Failed to read records from {}",
-                &args.ru_in.to_string_lossy()
-            )
-        })?
-        .records();
-
-    // If output paths have been specified, check if the are ok to use or use prefix constructors.
-    let mut output1: PathBuf = args
-        .r1_out
-        .unwrap_or(file_io::append_umi_to_path(&args.r1_in));
-    let mut output2: PathBuf = args
-        .r2_out
-        .unwrap_or(file_io::append_umi_to_path(&args.r2_in));
-
-    // set the correct extension.
-    output1 = file_io::rectify_extension(output1, &args.gzip)?;
-    output2 = file_io::rectify_extension(output2, &args.gzip)?;
-
-    // modify if output path according to compression settings and check if exists.
-    output1 = file_io::check_outputpath(output1, &args.force)?;
-    output2 = file_io::check_outputpath(output2, &args.force)?;
-
-    println!("Output 1 will be saved to: {}", output1.to_string_lossy());
-    println!("Output 2 will be saved to: {}", output2.to_string_lossy());
-
-    let mut write_output_r1 = file_io::create_writer(
-        output1,
-        &args.gzip,
-        &threads_per_task,
-        &args.compression_level,
-        None,
-    )?;
-    let mut write_output_r2 = file_io::create_writer(
-        output2,
-        &args.gzip,
-        &threads_per_task,
-        &args.compression_level,
-        None,
-    )?;
-
-    // Record counter
-    let mut counter: i32 = 0;
-
-    println!("Transferring UMIs to records...");
-
-    // Iterate over records in input files
-    for (r1_rec_res, ru_rec_res, r2_rec_res) in izip!(r1, ru, r2) {
-        let r1_rec = r1_rec_res?;
-        let r2_rec = r2_rec_res?;
-        let ru_rec = ru_rec_res?;
-
-        // Step counter
-        counter += 1;
-
-        if r1_rec.id().eq(ru_rec.id()) {
-            // Write to Output file
-            let read_nr = if edit_nr { Some(1) } else { None };
-
-            let r1_rec = match args.target_position {
-                UMIDestination::Header => {
-                    umi_to_record_header(r1_rec, ru_rec.seq(), args.delim.as_ref(), read_nr)
-                }
-                UMIDestination::Inline => {
-                    umi_to_record_seq(r1_rec, ru_rec.seq(), ru_rec.qual(), read_nr)
-                }
-            }?;
-
-            write_output_r1.write_record(r1_rec)?;
-        } else {
-            return Err(anyhow!(RuntimeErrors::ReadIDMismatch));
-        }
-
-        if r2_rec.id().eq(ru_rec.id()) {
-            // Write to Output file
-            let read_nr = if edit_nr { Some(2) } else { None };
-
-            let r2_rec = match args.target_position {
-                UMIDestination::Header => {
-                    umi_to_record_header(r2_rec, ru_rec.seq(), args.delim.as_ref(), read_nr)
-                }
-                UMIDestination::Inline => {
-                    umi_to_record_seq(r2_rec, ru_rec.seq(), ru_rec.qual(), read_nr)
-                }
-            }?;
-
-            write_output_r2.write_record(r2_rec)?;
-        } else {
-            return Err(anyhow!(RuntimeErrors::ReadIDMismatch));
-        }
+pub fn run(mut args: OptsExternal) -> Result<TransferSummary> {
+    // Fill in all settings that were not given on the command line from the configuration file.
+    args.settings = config::apply_config(args.settings)?;
+    run_merged(args)
+}
+
+// Transfers the UMIs with settings that are already merged with the configuration file, which
+// the batch does once for all samples.
+pub fn run_merged(args: OptsExternal) -> Result<TransferSummary> {
+    let settings = args.settings;
+    let overwrite = settings.overwrite_policy();
+
+    let umis = if let Some(table) = args.umi_table {
+        UmiInput::Table(table)
+    } else if let Some((read, length)) = args.umi_in_read {
+        UmiInput::Read(read, length)
+    } else if args.umi_from_index {
+        UmiInput::Index
+    } else {
+        UmiInput::FastQ(args.ru_in)
+    };
+
+    let mut transfer = UmiTransfer::with_umis(args.r1_in, args.r2_in, umis)
+        .output_naming(args.naming.naming()?)
+        .destination(settings.target_position.unwrap_or(UMIDestination::Header))
+        .correct_read_numbers(settings.edit_nr.unwrap_or(false))
+        .delim(settings.delim.as_deref().unwrap_or(":"))
+        .header_template(
+            settings
+                .header_template
+                .as_deref()
+                .unwrap_or(DEFAULT_TEMPLATE),
+        )
+        .on_existing_umi(settings.on_existing_umi.unwrap_or(ExistingUMI::Error))
+        .inline_end(settings.inline_end.unwrap_or(InlineEnd::FivePrime))
+        .inline_reads(settings.inline_reads.unwrap_or(InlineReads::Both))
+        .gzip(settings.gzip.unwrap_or(false))
+        .overwrite(overwrite)
+        .strict(settings.strict.unwrap_or(false))
+        .resume(args.resume)
+        .keep_partial(args.keep_partial)
+        .verbose(true);
+
+    // Settings without default value are only passed on if they were given.
+    if let Some(spacer) = settings.spacer {
+        transfer = transfer.spacer(spacer);
+    }
+    if let Some(spacer_qual) = settings.spacer_qual {
+        transfer = transfer.spacer_qual(spacer_qual);
+    }
+    if let Some(encoding) = settings.convert_quals_to {
+        transfer = transfer.convert_quals_to(encoding);
+    }
+    if let Some(level) = settings.compression_level {
+        transfer = transfer.compression_level(level);
+    }
+    if let Some(threads) = settings.num_threads {
+        transfer = transfer.threads(threads);
     }
-    println!("Processed {:?} records", counter);
-    Ok(counter)
+    if let Some(output) = args.r1_out {
+        transfer = transfer.out(output);
+    }
+    if let Some(output) = args.r2_out {
+        transfer = transfer.out2(output);
+    }
+    if let Some(output) = args.ru_out {
+        transfer = transfer.out_umi(output);
+    }
+    if let Some(sample) = args.sample {
+        transfer = transfer.sample(sample);
+    }
+    if let Some(every) = args.checkpoint {
+        transfer = transfer.checkpoint(every);
+    }
+
+    let summary = transfer.run()?;
+    println!("Processed {:?} records", summary.records);
+    Ok(summary)
 }
@@ -0,0 +1,436 @@
+use anyhow::{anyhow, Result};
+use bio::io::fastq::Record;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_io;
+use crate::read_editing::{pair_name, same_read};
+use crate::read_header::casava_index;
+use crate::umi_errors::RuntimeErrors;
+
+// Quality character of UMIs from sources that do not provide qualities, the same as for spacers.
+pub const DEFAULT_UMI_QUAL: u8 = b'I';
+
+// UMI of a read pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Umi {
+    pub seq: Vec<u8>,
+    pub qual: Vec<u8>,
+    // Description of the UMI record, if the source has one. It is written to the UMI output.
+    pub desc: Option<String>,
+}
+
+impl Umi {
+    fn with_default_qual(seq: &[u8]) -> Self {
+        Umi {
+            seq: seq.to_vec(),
+            qual: vec![DEFAULT_UMI_QUAL; seq.len()],
+            desc: None,
+        }
+    }
+}
+
+// Provides the UMI for each read pair, in the order of the read files.
+pub trait UmiSource {
+    // Returns the UMI of the next read pair. Sources that take the UMI from the reads themselves
+    // remove it from the read.
+    fn next_umi(&mut self, r1: &mut Record, r2: &mut Record) -> Result<Umi>;
+
+    // Skips the UMIs of read pairs that were already processed, e.g. when a run is resumed.
+    fn skip(&mut self, _records: usize) -> Result<()> {
+        Ok(())
+    }
+
+    // Whether UMIs are left after the last read pair, i.e. the read files ended early.
+    fn remaining(&mut self) -> bool {
+        false
+    }
+}
+
+// Read from which a UMI at the start of the sequence is taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UmiRead {
+    R1,
+    R2,
+}
+
+// Where the UMIs are taken from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UmiInput {
+    // Separate FastQ file(s) with a UMI record for every read pair.
+    FastQ(Vec<PathBuf>),
+    // Tab-separated table of read names and UMIs, optionally with qualities.
+    Table(PathBuf),
+    // The given number of bases at the start of one of the reads.
+    Read(UmiRead, usize),
+    // The last index of the Casava comment of read 1, e.g. the UMI read as I2.
+    Index,
+}
+
+// Parses the READ:LENGTH argument of --umi-in-read, e.g. `r1:8`.
+pub fn parse_umi_in_read(value: &str) -> Result<(UmiRead, usize), String> {
+    let invalid =
+        || format!("{value} is not valid. Please specify the read and length, e.g. r1:8.");
+    let (read, length) = value.split_once(':').ok_or_else(invalid)?;
+    let read = match read.to_lowercase().as_str() {
+        "r1" => UmiRead::R1,
+        "r2" => UmiRead::R2,
+        _ => return Err(invalid()),
+    };
+    match length.parse() {
+        Ok(length) if length > 0 => Ok((read, length)),
+        _ => Err(invalid()),
+    }
+}
+
+impl UmiInput {
+    // Opens the source. The UMI separator of the template is needed to match reads that already carry a UMI.
+    pub fn open(&self, separator: &str, strict: bool) -> Result<Box<dyn UmiSource>> {
+        Ok(match self {
+            UmiInput::FastQ(paths) => Box::new(FastqUmis::new(paths, separator, strict)?),
+            UmiInput::Table(path) => Box::new(TableUmis::read(path)?),
+            UmiInput::Read(read, length) => Box::new(ReadUmis::new(*read, *length)),
+            UmiInput::Index => Box::new(IndexUmis),
+        })
+    }
+
+    // Files that are read by the source.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            UmiInput::FastQ(paths) => paths.clone(),
+            UmiInput::Table(path) => vec![path.clone()],
+            UmiInput::Read(..) | UmiInput::Index => Vec::new(),
+        }
+    }
+}
+
+// UMIs from separate FastQ files, whose records must be in the same order as the reads.
+pub struct FastqUmis {
+    records: Box<dyn Iterator<Item = Result<Record>>>,
+    paths: Vec<PathBuf>,
+    separator: String,
+    counter: usize,
+}
+
+impl FastqUmis {
+    pub fn new(paths: &[PathBuf], separator: &str, strict: bool) -> Result<Self> {
+        let records = file_io::read_fastqs(paths, strict).map_err(|e| {
+            e.context(format!(
+                "Failed to read records from {}",
+                file_io::display_paths(paths)
+            ))
+        })?;
+        Ok(FastqUmis {
+            records: Box::new(records),
+            paths: paths.to_vec(),
+            separator: separator.to_string(),
+            counter: 0,
+        })
+    }
+}
+
+impl UmiSource for FastqUmis {
+    fn next_umi(&mut self, r1: &mut Record, r2: &mut Record) -> Result<Umi> {
+        let record = self.records.next().ok_or_else(|| {
+            anyhow!(RuntimeErrors::TruncatedInput(
+                file_io::display_paths(&self.paths),
+                self.counter
+            ))
+        })??;
+        self.counter += 1;
+        let umi_len = record.seq().len();
+        if !same_read(r1.id(), record.id(), &self.separator, umi_len)
+            || !same_read(r2.id(), record.id(), &self.separator, umi_len)
+        {
+            return Err(anyhow!(RuntimeErrors::ReadIDMismatch));
+        }
+        Ok(Umi {
+            seq: record.seq().to_vec(),
+            qual: record.qual().to_vec(),
+            desc: record.desc().map(str::to_string),
+        })
+    }
+
+    fn skip(&mut self, records: usize) -> Result<()> {
+        for _ in 0..records {
+            self.records.next().transpose()?;
+            self.counter += 1;
+        }
+        Ok(())
+    }
+
+    fn remaining(&mut self) -> bool {
+        self.records.next().is_some()
+    }
+}
+
+// UMIs looked up by read name in a table with the columns read name, UMI and optionally qualities.
+pub struct TableUmis {
+    umis: HashMap<String, Umi>,
+}
+
+impl TableUmis {
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|source| {
+            anyhow!(RuntimeErrors::FileNotFound {
+                path: path.into(),
+                source
+            })
+        })?;
+        Self::parse(&content, path)
+    }
+
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        let mut umis = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let invalid = |reason: &str| {
+                anyhow!(RuntimeErrors::UMITableInvalid(
+                    path.into(),
+                    index + 1,
+                    reason.into()
+                ))
+            };
+            // Empty lines and comments are skipped.
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let umi = match fields[..] {
+                [_, seq] => Umi::with_default_qual(seq.as_bytes()),
+                [_, seq, qual] if seq.len() == qual.len() => Umi {
+                    seq: seq.as_bytes().to_vec(),
+                    qual: qual.as_bytes().to_vec(),
+                    desc: None,
+                },
+                [_, _, _] => {
+                    return Err(invalid(
+                        "the UMI and its qualities must have the same length",
+                    ))
+                }
+                _ => {
+                    return Err(invalid(
+                        "expected the read name, the UMI and optionally its qualities separated by tabs",
+                    ))
+                }
+            };
+            if umi.seq.is_empty() {
+                return Err(invalid("the UMI is empty"));
+            }
+            let name = pair_name(fields[0]).to_string();
+            if umis.insert(name, umi).is_some() {
+                return Err(invalid("the read name occurs more than once"));
+            }
+        }
+        Ok(TableUmis { umis })
+    }
+}
+
+impl UmiSource for TableUmis {
+    fn next_umi(&mut self, r1: &mut Record, _r2: &mut Record) -> Result<Umi> {
+        self.umis.get(pair_name(r1.id())).cloned().ok_or_else(|| {
+            anyhow!(RuntimeErrors::UMIUnavailable(
+                r1.id().to_string(),
+                "the read is not listed in the UMI table".to_string()
+            ))
+        })
+    }
+}
+
+// UMIs at the start of one of the reads, which are trimmed from the read.
+pub struct ReadUmis {
+    read: UmiRead,
+    length: usize,
+}
+
+impl ReadUmis {
+    pub fn new(read: UmiRead, length: usize) -> Self {
+        ReadUmis { read, length }
+    }
+}
+
+impl UmiSource for ReadUmis {
+    fn next_umi(&mut self, r1: &mut Record, r2: &mut Record) -> Result<Umi> {
+        let record = match self.read {
+            UmiRead::R1 => r1,
+            UmiRead::R2 => r2,
+        };
+        if record.seq().len() < self.length {
+            return Err(anyhow!(RuntimeErrors::UMIUnavailable(
+                record.id().to_string(),
+                format!("the read is shorter than the UMI length {}", self.length)
+            )));
+        }
+        // Without --strict, the parser accepts records with fewer quality scores than bases.
+        if record.qual().len() < self.length {
+            return Err(anyhow!(RuntimeErrors::UMIUnavailable(
+                record.id().to_string(),
+                format!(
+                    "the read has fewer quality scores than the UMI length {}",
+                    self.length
+                )
+            )));
+        }
+        let umi = Umi {
+            seq: record.seq()[..self.length].to_vec(),
+            qual: record.qual()[..self.length].to_vec(),
+            desc: None,
+        };
+        *record = Record::with_attrs(
+            record.id(),
+            record.desc(),
+            &record.seq()[self.length..],
+            &record.qual()[self.length..],
+        );
+        Ok(umi)
+    }
+}
+
+// UMIs in the index field of the Casava comment of read 1. With dual indices, the last index is
+// used, since the UMI is commonly read as second index read.
+pub struct IndexUmis;
+
+impl UmiSource for IndexUmis {
+    fn next_umi(&mut self, r1: &mut Record, _r2: &mut Record) -> Result<Umi> {
+        let index = casava_index(r1.desc())
+            .and_then(|index| index.rsplit('+').next())
+            .filter(|umi| {
+                !umi.is_empty()
+                    && umi
+                        .bytes()
+                        .all(|b| matches!(b, b'A' | b'C' | b'G' | b'T' | b'N'))
+            });
+        match index {
+            Some(umi) => Ok(Umi::with_default_qual(umi.as_bytes())),
+            None => Err(anyhow!(RuntimeErrors::UMIUnavailable(
+                r1.id().to_string(),
+                "the read has no Casava comment with an index sequence".to_string()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn read_pair(id: &str, desc: Option<&str>) -> (Record, Record) {
+        (
+            Record::with_attrs(id, desc, b"ACCAGCTAGG", b"ABCDEFGHIJ"),
+            Record::with_attrs(id, desc, b"TTTTTTTTTT", b"FFFFFFFFFF"),
+        )
+    }
+
+    #[test]
+    fn test_fastq_umis() {
+        let mut source =
+            FastqUmis::new(&[PathBuf::from("tests/seqdata/umi.fq")], ":", false).unwrap();
+        let (mut r1, mut r2) = read_pair("SCILIFELAB:500:NGISTLM:1:1101:19994:1016", None);
+        let umi = source.next_umi(&mut r1, &mut r2).unwrap();
+        assert_eq!(umi.seq, b"CCTGAGACC");
+        assert_eq!(umi.qual, b"FFFFFFFFF");
+        assert_eq!(umi.desc.as_deref(), Some("2:N:0:GCTTCAGGGT+AAGGTAGCGT"));
+        assert!(source.remaining());
+
+        let result = source.next_umi(&mut r1, &mut r2);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("IDs of UMI and read records mismatch"));
+    }
+
+    #[test]
+    fn test_table_umis() {
+        let table = "# read\tumi\tqual\nREAD1/1\tACGT\nREAD2\tGGCC\tFF#F\n\n";
+        let mut source = TableUmis::parse(table, Path::new("umis.tsv")).unwrap();
+
+        let (mut r1, mut r2) = read_pair("READ1/1", None);
+        let umi = source.next_umi(&mut r1, &mut r2).unwrap();
+        assert_eq!(umi.seq, b"ACGT");
+        assert_eq!(umi.qual, b"IIII");
+
+        let (mut r1, mut r2) = read_pair("READ2/1", None);
+        assert_eq!(source.next_umi(&mut r1, &mut r2).unwrap().qual, b"FF#F");
+
+        let (mut r1, mut r2) = read_pair("READ3", None);
+        assert!(source
+            .next_umi(&mut r1, &mut r2)
+            .unwrap_err()
+            .to_string()
+            .contains("not listed in the UMI table"));
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        for (table, reason) in [
+            (
+                "READ1 ACGT\n",
+                "Line 1 of UMI table umis.tsv is invalid: expected the read name",
+            ),
+            (
+                "READ1\tACGT\tFF\n",
+                "Line 1 of UMI table umis.tsv is invalid: the UMI and its qualities",
+            ),
+            (
+                "READ1\tACGT\nREAD1\tGGCC\n",
+                "Line 2 of UMI table umis.tsv is invalid: the read name occurs",
+            ),
+            ("READ1\t\n", "the UMI is empty"),
+        ] {
+            let result = TableUmis::parse(table, Path::new("umis.tsv"));
+            let message = result.err().unwrap().to_string();
+            assert!(message.contains(reason), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_read_umis() {
+        let mut source = ReadUmis::new(UmiRead::R1, 4);
+        let (mut r1, mut r2) = read_pair("READ1", Some("1:N:0:ACGT"));
+        let umi = source.next_umi(&mut r1, &mut r2).unwrap();
+        assert_eq!(umi.seq, b"ACCA");
+        assert_eq!(umi.qual, b"ABCD");
+        // The UMI is trimmed from the read.
+        assert_eq!(r1.seq(), b"GCTAGG");
+        assert_eq!(r1.qual(), b"EFGHIJ");
+        assert_eq!(r1.desc(), Some("1:N:0:ACGT"));
+        assert_eq!(r2.seq(), b"TTTTTTTTTT");
+
+        let mut source = ReadUmis::new(UmiRead::R2, 11);
+        let (mut r1, mut r2) = read_pair("READ1", None);
+        assert!(source.next_umi(&mut r1, &mut r2).is_err());
+
+        // The quality line of the read is truncated.
+        let mut source = ReadUmis::new(UmiRead::R1, 4);
+        let mut r1 = Record::with_attrs("READ1", None, b"ACCAGCTAGG", b"ABC");
+        let mut r2 = Record::with_attrs("READ1", None, b"TTTTTTTTTT", b"FFFFFFFFFF");
+        assert!(source
+            .next_umi(&mut r1, &mut r2)
+            .unwrap_err()
+            .to_string()
+            .contains("the read has fewer quality scores than the UMI length 4"));
+    }
+
+    #[test]
+    fn test_index_umis() {
+        let (mut r1, mut r2) = read_pair("READ1", Some("1:N:0:GCTTCAGGGT+AAGGTAGCGT"));
+        let umi = IndexUmis.next_umi(&mut r1, &mut r2).unwrap();
+        assert_eq!(umi.seq, b"AAGGTAGCGT");
+        assert_eq!(umi.qual, b"IIIIIIIIII");
+
+        let (mut r1, mut r2) = read_pair("READ1", Some("1:N:0:2"));
+        assert!(IndexUmis.next_umi(&mut r1, &mut r2).is_err());
+        let (mut r1, mut r2) = read_pair("READ1/1", None);
+        assert!(IndexUmis.next_umi(&mut r1, &mut r2).is_err());
+    }
+
+    #[test]
+    fn test_parse_umi_in_read() {
+        assert_eq!(parse_umi_in_read("r1:8"), Ok((UmiRead::R1, 8)));
+        assert_eq!(parse_umi_in_read("R2:12"), Ok((UmiRead::R2, 12)));
+        assert!(parse_umi_in_read("r3:8").is_err());
+        assert!(parse_umi_in_read("r1:0").is_err());
+        assert!(parse_umi_in_read("r1").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Context, Result};
+use bio::io::fastq::Record;
+use clap::Parser;
+use std::path::PathBuf;
+
+use umi_transfer::file_io;
+use umi_transfer::read_editing::same_read;
+use umi_transfer::umi_errors::RuntimeErrors;
+
+// Only the first problems are printed in detail, the remainder is just counted.
+const MAX_REPORTED_PROBLEMS: usize = 10;
+
+#[derive(Debug, Parser)]
+pub struct OptsValidate {
+    #[clap(
+        long = "in",
+        required = true,
+        num_args = 1..,
+        help = "[REQUIRED] Input file 1 with reads. Multiple files, e.g. lanes, are processed in the given order.
+    \n "
+    )]
+    r1_in: Vec<PathBuf>,
+    #[clap(
+        long = "in2",
+        required = true,
+        num_args = 1..,
+        help = "[REQUIRED] Input file 2 with reads. Multiple files, e.g. lanes, are processed in the given order.
+    \n "
+    )]
+    r2_in: Vec<PathBuf>,
+    #[clap(
+        short = 'u',
+        long = "umi",
+        required = true,
+        num_args = 1..,
+        help = "[REQUIRED] Input file with UMI. Multiple files, e.g. lanes, are processed in the given order.
+        \n"
+    )]
+    ru_in: Vec<PathBuf>,
+}
+
+// Keeps track of the problems found during validation.
+struct ValidationReport {
+    problems: usize,
+}
+
+impl ValidationReport {
+    fn report(&mut self, message: String) {
+        self.problems += 1;
+        if self.problems <= MAX_REPORTED_PROBLEMS {
+            eprintln!("{}", message);
+        } else if self.problems == MAX_REPORTED_PROBLEMS + 1 {
+            eprintln!("Further problems will be counted, but not shown.");
+        }
+    }
+
+    fn check_lengths(&mut self, record: &Record, path: &str, counter: u64) {
+        if record.seq().len() != record.qual().len() {
+            self.report(format!(
+                "Record {} ({}) in {} has {} bases, but {} quality scores.",
+                counter,
+                record.id(),
+                path,
+                record.seq().len(),
+                record.qual().len()
+            ));
+        }
+    }
+
+    fn check_ids(&mut self, read: &Record, umi: &Record, path: &str, counter: u64) {
+        // Reads are matched like during the transfer, which ignores legacy read number suffixes
+        // and UMIs that were added with the default delimiter.
+        if !same_read(read.id(), umi.id(), ":", umi.seq().len()) {
+            self.report(format!(
+                "Record {} in {} has ID {}, but the UMI record has ID {}.",
+                counter,
+                path,
+                read.id(),
+                umi.id()
+            ));
+        }
+    }
+}
+
+pub fn run(args: OptsValidate) -> Result<u64> {
+    // Like in the transfer, every read file must be paired with a UMI file, e.g. lane by lane.
+    if args.r1_in.len() != args.ru_in.len() || args.r2_in.len() != args.ru_in.len() {
+        return Err(anyhow!(RuntimeErrors::InputCountMismatch(
+            args.r1_in.len(),
+            args.r2_in.len(),
+            args.ru_in.len()
+        )));
+    }
+
+    // Read FastQ records from input files
+    let mut r1 = file_io::read_fastqs(&args.r1_in, false).with_context(|| {
+        format!(
+            "Failed to read records from {}",
+            file_io::display_paths(&args.r1_in)
+        )
+    })?;
+    let mut r2 = file_io::read_fastqs(&args.r2_in, false).with_context(|| {
+        format!(
+            "Failed to read records from {}",
+            file_io::display_paths(&args.r2_in)
+        )
+    })?;
+    let mut ru = file_io::read_fastqs(&args.ru_in, false).with_context(|| {
+        format!(
+            "Failed to read records from {}",
+            file_io::display_paths(&args.ru_in)
+        )
+    })?;
+
+    // Names of the inputs for the problem reports.
+    let r1_name = file_io::display_paths(&args.r1_in);
+    let r2_name = file_io::display_paths(&args.r2_in);
+    let ru_name = file_io::display_paths(&args.ru_in);
+
+    let mut report = ValidationReport { problems: 0 };
+
+    // Record counter
+    let mut counter: u64 = 0;
+
+    println!("Validating input files...");
+
+    // Unlike izip!(), advance all three inputs manually, so that files of unequal length are detected.
+    loop {
+        let (r1_rec, r2_rec, ru_rec) = match (r1.next(), r2.next(), ru.next()) {
+            (None, None, None) => break,
+            (Some(r1_rec), Some(r2_rec), Some(ru_rec)) => (r1_rec?, r2_rec?, ru_rec?),
+            (r1_rec, r2_rec, ru_rec) => {
+                // At least one of the files ended prematurely: Name all that did.
+                for (ended, path) in [
+                    (r1_rec.is_none(), &r1_name),
+                    (r2_rec.is_none(), &r2_name),
+                    (ru_rec.is_none(), &ru_name),
+                ] {
+                    if ended {
+                        report.report(format!(
+                            "{} ended after {} records, while other input files contain more.",
+                            path, counter
+                        ));
+                    }
+                }
+                break;
+            }
+        };
+
+        // Step counter
+        counter += 1;
+
+        report.check_ids(&r1_rec, &ru_rec, &r1_name, counter);
+        report.check_ids(&r2_rec, &ru_rec, &r2_name, counter);
+
+        report.check_lengths(&r1_rec, &r1_name, counter);
+        report.check_lengths(&r2_rec, &r2_name, counter);
+        report.check_lengths(&ru_rec, &ru_name, counter);
+    }
+
+    println!("Validated {:?} records", counter);
+
+    if report.problems > 0 {
+        return Err(anyhow!(RuntimeErrors::ValidationFailed(report.problems)));
+    }
+
+    println!("Input files are consistent.");
+    Ok(counter)
+}
@@ -0,0 +1,247 @@
+use assert_fs::prelude::*;
+use auxiliary::verify_file_contents;
+use predicates::prelude::*;
+use std::error::Error;
+
+#[path = "auxiliary.rs"]
+mod auxiliary;
+
+type TestResult = Result<(), Box<dyn Error>>;
+
+#[test]
+fn batch_fails_without_sheet() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    cmd.arg("batch");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: the following required arguments were not provided",
+        ))
+        .stderr(predicate::str::contains("--sheet <SHEET>"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn batch_processes_all_samples() -> TestResult {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    let test_output = test_output.unwrap();
+
+    let sheet = temp_dir.child("samples.tsv");
+    sheet.write_str(&format!(
+        "sample\tR1\tR2\tUMI\tprefix\nA\t{r1}\t{r2}\t{umi}\t{out}/A\nB\t{r1}\t{r2}\t{umi}\t{out}/B\n",
+        r1 = test_files.read1.display(),
+        r2 = test_files.read2.display(),
+        umi = test_files.umi.display(),
+        out = temp_dir.path().display(),
+    ))?;
+
+    cmd.arg("batch")
+        .arg("--sheet")
+        .arg(sheet.path())
+        .arg("--jobs")
+        .arg("2")
+        .arg("--threads")
+        .arg("5");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Processing 2 samples, 2 at a time with 2 threads each.",
+        ))
+        .stdout(predicate::str::contains("A: processed 10 records"))
+        .stdout(predicate::str::contains("B: processed 10 records"));
+
+    for sample in ["A", "B"] {
+        verify_file_contents(
+            &temp_dir.path().join(format!("{sample}_R1_with_UMIs.fastq")),
+            &test_output.header_read1,
+        )?;
+        verify_file_contents(
+            &temp_dir.path().join(format!("{sample}_R2_with_UMIs.fastq")),
+            &test_output.header_read2,
+        )?;
+    }
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn batch_reports_failed_samples() -> TestResult {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let sheet = temp_dir.child("samples.csv");
+    sheet.write_str(&format!(
+        "A,{r1},{r2},{umi}\nB,{missing},{r2},{umi},{out}/B\n",
+        r1 = test_files.read1_gz.display(),
+        r2 = test_files.read2_gz.display(),
+        umi = test_files.umi_gz.display(),
+        missing = temp_dir.path().join("missing.fq").display(),
+        out = temp_dir.path().display(),
+    ))?;
+
+    cmd.arg("batch").arg("--sheet").arg(sheet.path()).arg("-z");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("A: processed 10 records"))
+        .stderr(predicate::str::contains("B: FAILED"))
+        .stderr(predicate::str::contains("missing.fq does not exist"))
+        .stderr(predicate::str::contains("Failed to process the batch"))
+        .stderr(predicate::str::contains(
+            "1 of 2 samples could not be processed",
+        ));
+
+    temp_dir
+        .child("read1_with_UMIs.fq.gz")
+        .assert(predicate::path::exists());
+    temp_dir
+        .child("B_R1_with_UMIs.fastq.gz")
+        .assert(predicate::path::missing());
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn batch_discovers_samples_in_illumina_directory() -> TestResult {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    let test_output = test_output.unwrap();
+
+    // Arrange the test data like bcl2fastq would name it.
+    let run_dir = temp_dir.child("run");
+    run_dir.create_dir_all()?;
+    std::fs::copy(
+        &test_files.read1,
+        run_dir.path().join("Sample_S1_L001_R1_001.fastq"),
+    )?;
+    std::fs::copy(
+        &test_files.umi,
+        run_dir.path().join("Sample_S1_L001_R2_001.fastq"),
+    )?;
+    std::fs::copy(
+        &test_files.read2,
+        run_dir.path().join("Sample_S1_L001_R3_001.fastq"),
+    )?;
+
+    cmd.arg("batch")
+        .arg("--dir")
+        .arg(run_dir.path())
+        .arg("--umi-read")
+        .arg("R2");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processing 1 samples"))
+        .stdout(predicate::str::contains(
+            "Sample_S1_L001: processed 10 records",
+        ));
+
+    verify_file_contents(
+        &run_dir.path().join("Sample_S1_L001_R1_001_with_UMIs.fastq"),
+        &test_output.header_read1,
+    )?;
+    verify_file_contents(
+        &run_dir.path().join("Sample_S1_L001_R3_001_with_UMIs.fastq"),
+        &test_output.header_read2,
+    )?;
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn batch_processes_complete_read_groups_only() -> TestResult {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    // The UMI read of the second sample is missing.
+    let run_dir = temp_dir.child("run");
+    run_dir.create_dir_all()?;
+    for (source, name) in [
+        (&test_files.read1, "Sample_S1_L001_R1_001.fastq"),
+        (&test_files.umi, "Sample_S1_L001_R2_001.fastq"),
+        (&test_files.read2, "Sample_S1_L001_R3_001.fastq"),
+        (&test_files.read1, "Other_S2_L001_R1_001.fastq"),
+        (&test_files.read2, "Other_S2_L001_R3_001.fastq"),
+    ] {
+        std::fs::copy(source, run_dir.path().join(name))?;
+    }
+
+    cmd.arg("batch").arg("--dir").arg(run_dir.path());
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Processing 2 samples"))
+        .stdout(predicate::str::contains(
+            "Sample_S1_L001: processed 10 records",
+        ))
+        .stderr(predicate::str::contains(
+            "Other_S2_L001: FAILED - Read group Other_S2_L001 is incomplete: no R2 file",
+        ))
+        .stderr(predicate::str::contains(
+            "1 of 2 samples could not be processed",
+        ));
+
+    run_dir
+        .child("Sample_S1_L001_R1_001_with_UMIs.fastq")
+        .assert(predicate::path::exists());
+    run_dir
+        .child("Other_S2_L001_R1_001_with_UMIs.fastq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn batch_writes_merged_lanes_to_outdir() -> TestResult {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let run_dir = temp_dir.child("run");
+    run_dir.create_dir_all()?;
+    for lane in ["L001", "L002"] {
+        for (source, read) in [
+            (&test_files.read1, "R1"),
+            (&test_files.umi, "R2"),
+            (&test_files.read2, "R3"),
+        ] {
+            std::fs::copy(
+                source,
+                run_dir
+                    .path()
+                    .join(format!("Sample_S1_{lane}_{read}_001.fastq")),
+            )?;
+        }
+    }
+    let out_dir = temp_dir.child("out");
+    out_dir.create_dir_all()?;
+
+    cmd.arg("batch")
+        .arg("--dir")
+        .arg(run_dir.path())
+        .arg("--merge-lanes")
+        .arg("--outdir")
+        .arg(out_dir.path())
+        .arg("-z");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Sample_S1: processed 20 records"));
+
+    // The merged outputs are named after the sample like all other outputs.
+    out_dir
+        .child("Sample_S1_R1_with_UMIs.fastq.gz")
+        .assert(predicate::path::exists());
+    out_dir
+        .child("Sample_S1_R2_with_UMIs.fastq.gz")
+        .assert(predicate::path::exists());
+    run_dir
+        .child("Sample_S1_R1_with_UMIs.fastq.gz")
+        .assert(predicate::path::missing());
+
+    temp_dir.close()?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[path = "auxiliary.rs"]
+mod auxiliary;
+
+#[test]
+fn config_show_prints_defaults() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    cmd.arg("config").arg("show");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Effective settings:"))
+        .stdout(predicate::str::contains("position:          header"))
+        .stdout(predicate::str::contains("gzip:              false"))
+        .stdout(predicate::str::contains("compression_level: default"))
+        .stdout(predicate::str::contains("delim:             \":\""));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn config_show_respects_precedence() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let config = temp_dir.child("umi-transfer.toml");
+    config
+        .write_str("position = \"inline\"\ngzip = true\ncompression_level = 5\ndelim = \"_\"\nthreads = 3\n")
+        .unwrap();
+
+    // The environment variable overrides the file, the command line overrides both.
+    cmd.env("UMI_TRANSFER_COMPRESSION_LEVEL", "7")
+        .env("UMI_TRANSFER_DELIM", "-")
+        .arg("config")
+        .arg("show")
+        .arg("--config")
+        .arg(config.path())
+        .arg("--delim")
+        .arg("+");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("umi-transfer.toml"))
+        .stdout(predicate::str::contains("position:          inline"))
+        .stdout(predicate::str::contains("gzip:              true"))
+        .stdout(predicate::str::contains("compression_level: 7"))
+        .stdout(predicate::str::contains("threads:           3"))
+        .stdout(predicate::str::contains("delim:             \"+\""));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn config_flags_can_be_switched_off() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let config = temp_dir.child("umi-transfer.toml");
+    config
+        .write_str("gzip = true\ncorrect_numbers = true\nstrict = true\n")
+        .unwrap();
+
+    cmd.env("UMI_TRANSFER_GZIP", "false")
+        .arg("config")
+        .arg("show")
+        .arg("--config")
+        .arg(config.path())
+        .arg("--correct_numbers=false");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("gzip:              false"))
+        .stdout(predicate::str::contains("correct_numbers:   false"))
+        .stdout(predicate::str::contains("strict:            true"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn config_keeps_the_flag_syntax() {
+    // Flags take an optional value only with `=`, so bundled short flags work as before.
+    for (args, overwrite) in [
+        (vec!["-cz"], "prompt"),
+        (vec!["-czf"], "always"),
+        (vec!["-c", "-z", "-f"], "always"),
+        (vec!["--correct_numbers", "--gzip", "--force"], "always"),
+        (
+            vec!["--correct_numbers=true", "--gzip=yes", "-f=1"],
+            "always",
+        ),
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!();
+        cmd.arg("config").arg("show").args(&args);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("correct_numbers:   true"))
+            .stdout(predicate::str::contains("gzip:              true"))
+            .stdout(predicate::str::contains(format!(
+                "overwrite:         {}",
+                overwrite
+            )));
+    }
+
+    // Like before, a value separated by a space is not taken by the flag.
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!();
+    cmd.arg("config").arg("show").arg("-c").arg("true");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unexpected argument 'true'"));
+}
+
+#[test]
+fn config_fails_with_invalid_file() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let config = temp_dir.child("umi-transfer.yaml");
+    config.write_str("gzip: true\nlevel: 9\n").unwrap();
+
+    cmd.arg("config")
+        .arg("show")
+        .arg("--config")
+        .arg(config.path());
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to show the configuration"))
+        .stderr(predicate::str::contains("umi-transfer.yaml is invalid"))
+        .stderr(predicate::str::contains("unknown field `level`"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_uses_settings_from_config_file_and_environment() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let config = temp_dir.child("umi-transfer.yml");
+    config.write_str("gzip: true\n").unwrap();
+
+    cmd.env("UMI_TRANSFER_CONFIG", config.path())
+        .arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    temp_dir
+        .child("read1_with_UMIs.fq.gz")
+        .assert(predicate::path::exists());
+    temp_dir
+        .child("read2_with_UMIs.fq.gz")
+        .assert(predicate::path::exists());
+
+    temp_dir.close().unwrap();
+}
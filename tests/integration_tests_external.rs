@@ -16,6 +16,7 @@ fn external_fails_without_arguments() {
 
     cmd.assert()
         .failure()
+        .code(2)
         .stderr(predicate::str::contains(
             "error: the following required arguments were not provided",
         ))
@@ -326,5 +327,1352 @@ fn external_fails_on_read_id_mismatch() {
             "Please provide sorted files as input",
         ));
 
+    // Partially written outputs are removed.
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+    temp_dir
+        .child("read1_with_UMIs.fq.tmp")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn external_fails_on_full_disk() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1_gz)
+        .arg("--in2")
+        .arg(test_files.read2_gz)
+        .arg("--umi")
+        .arg(test_files.umi_gz)
+        .arg("--out")
+        .arg("/dev/full")
+        .arg("--force");
+
+    // The records are buffered, so the error is only noticed when the output is finished.
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Output file /dev/full could not be completed.",
+        ))
+        .stderr(predicate::str::contains("No space left on device"));
+
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_keeps_partial_outputs_on_request() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1_gz)
+        .arg("--in2")
+        .arg(test_files.read2_gz)
+        .arg("--umi")
+        .arg(test_files.umi_shuffle_gz)
+        .arg("--keep-partial");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "IDs of UMI and read records mismatch",
+    ));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+    temp_dir
+        .child("read1_with_UMIs.fq.tmp")
+        .assert(predicate::path::exists());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_multiple_input_files() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(&test_files.read1)
+        .arg(&test_files.read1_gz)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg(&test_files.read2_gz)
+        .arg("--umi")
+        .arg(&test_files.umi)
+        .arg(&test_files.umi_gz)
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read2);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 20 records"));
+
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::path::exists());
+    temp_dir
+        .child("read2_out.fq")
+        .assert(predicate::path::exists());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_unequal_number_of_input_files() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(&test_files.read1)
+        .arg(&test_files.read1_gz)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg(&test_files.read2_gz)
+        .arg("--umi")
+        .arg(&test_files.umi);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to include the UMIs"))
+        .stderr(predicate::str::contains(
+            "Received 2 file(s) for read 1, 2 for read 2 and 1 for the UMIs",
+        ));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_header_template() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--sample")
+        .arg("P123_1001")
+        .arg("--header-template")
+        .arg("{id}_{umi} SM:{sample} RN:{read_nr} BC:{index}");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::str::starts_with(
+            "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016_",
+        ))
+        .assert(predicate::str::contains(
+            " SM:P123_1001 RN:2 BC:GCTTCAGGGT+AAGGTAGCGT\n",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_invalid_header_template() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--header-template")
+        .arg("{id}_{sample}");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to include the UMIs"))
+        .stderr(predicate::str::contains(
+            "Header template '{id}_{sample}' is invalid: {sample} requires a sample name",
+        ));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_handles_existing_umis() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(&test_files.read1)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg("--umi")
+        .arg(&test_files.umi);
+
+    cmd.assert().success();
+
+    // Processing the outputs again must not add a second UMI unnoticed.
+    let first_output1 = temp_dir.child("read1_with_UMIs.fq");
+    let first_output2 = temp_dir.child("read2_with_UMIs.fq");
+
+    let mut rerun = assert_cmd::cargo::cargo_bin_cmd!();
+    rerun
+        .arg("external")
+        .arg("--in")
+        .arg(first_output1.path())
+        .arg("--in2")
+        .arg(first_output2.path())
+        .arg("--umi")
+        .arg(&test_files.umi)
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read2);
+
+    rerun
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already contains a UMI"));
+
+    let mut rerun = assert_cmd::cargo::cargo_bin_cmd!();
+    rerun
+        .arg("external")
+        .arg("--in")
+        .arg(first_output1.path())
+        .arg("--in2")
+        .arg(first_output2.path())
+        .arg("--umi")
+        .arg(&test_files.umi)
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read2)
+        .arg("--on-existing-umi")
+        .arg("replace")
+        .arg("--force");
+
+    rerun.assert().success();
+
+    // Replacing the UMI with the same UMI reproduces the first output.
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::path::eq_file(first_output1.path()));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_detects_existing_umis_with_custom_template() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // READ1 carries a UMI from an earlier run with `{id}_{umi}`, READ2 merely ends with a short field.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1_GGCC 1:N:0:1\nACGT\n+\nFFFF\n@READ2:A 1:N:0:1\nACGT\n+\nFFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1_GGCC 3:N:0:1\nTTGG\n+\nFFFF\n@READ2:A 3:N:0:1\nTTGG\n+\nFFFF\n")
+        .unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1 2:N:0:1\nAATT\n+\nFFFF\n@READ2:A 2:N:0:1\nCCGG\n+\nFFFF\n")
+        .unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .arg("--header-template")
+        .arg("{id}_{umi} {desc}");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "READ1_GGCC already contains a UMI",
+    ));
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .arg("--header-template")
+        .arg("{id}_{umi} {desc}")
+        .arg("--on-existing-umi")
+        .arg("replace")
+        .arg("--force");
+
+    cmd.assert().success();
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1_AATT 1:N:0:1\nACGT\n+\nFFFF\n@READ2:A_CCGG 1:N:0:1\nACGT\n+\nFFFF\n",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_inline_position_three_prime_on_r1() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--position")
+        .arg("inline")
+        .arg("--inline-end")
+        .arg("3p")
+        .arg("--inline-reads")
+        .arg("r1")
+        .arg("--spacer")
+        .arg("TT")
+        .arg("--spacer-qual")
+        .arg("#");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::contains(
+            "GTTCTGGTTGGTTCCTGAGACC\n+\nF#FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF##FFFFFFFFF\n",
+        ));
+
+    // Read 2 does not receive the UMI.
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::path::eq_file(&test_files.read2));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_invalid_spacer() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--position")
+        .arg("inline")
+        .arg("--spacer")
+        .arg("ACGX");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Invalid inline spacer: spacer sequence ACGX may only contain A, C, G, T and N.",
+    ));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_umi_output() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out-umi")
+        .arg(temp_dir.path().join("umis.fq"))
+        .arg("--delim")
+        .arg("_");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("UMIs will be saved to:"))
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    // The UMI records are named like the reads with UMIs.
+    temp_dir.child("umis.fq").assert(predicate::str::starts_with(
+        "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016_CCTGAGACC 2:N:0:GCTTCAGGGT+AAGGTAGCGT\nCCTGAGACC\n+\nFFFFFFFFF\n",
+    ));
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::starts_with(
+            "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016_CCTGAGACC 1:N:0:",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_file_not_found() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(temp_dir.path().join("missing.fq"))
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi);
+
+    cmd.assert().code(3).stderr(predicate::str::contains(
+        "does not exist or is not readable",
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_file_exists() {
+    let (_cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    temp_dir
+        .child("read1_out.fq")
+        .write_str("existing")
+        .unwrap();
+
+    // The overwrite prompt requires a terminal, see external_fails_with_existing_output_file_and_no_force.
+    let mut cmd = StdCommand::new(assert_cmd::cargo::cargo_bin!());
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(test_files.new_output_read1);
+
+    let mut p = rexpect::session::spawn_command(cmd, Some(10000)).unwrap();
+    p.exp_string("read1_out.fq exists. Overwrite?").unwrap();
+    p.send_line("n").unwrap();
+    p.exp_string("exists, but must not be overwritten").unwrap();
+    match p.process.wait().unwrap() {
+        rexpect::process::wait::WaitStatus::Exited(_, code) => assert_eq!(code, 4),
+        status => panic!("unexpected status {:?}", status),
+    }
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::str::diff("existing"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_output_not_writeable() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(temp_dir.path().join("missing_dir").join("read1_out.fq"));
+
+    cmd.assert()
+        .code(5)
+        .stderr(predicate::str::contains("is missing or not writeable"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_input_not_readable() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(temp_dir.path())
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(test_files.new_output_read1);
+
+    cmd.assert()
+        .code(6)
+        .stderr(predicate::str::contains("could not be read"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_read_id_mismatch() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi_shuffle);
+
+    cmd.assert().code(7).stderr(predicate::str::contains(
+        "IDs of UMI and read records mismatch",
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_truncated_input() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // Only the first two records of read 2.
+    let read2 = std::fs::read_to_string(&test_files.read2).unwrap();
+    let truncated = read2.lines().take(8).collect::<Vec<_>>().join("\n") + "\n";
+    temp_dir
+        .child("read2_truncated.fq")
+        .write_str(&truncated)
+        .unwrap();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(temp_dir.path().join("read2_truncated.fq"))
+        .arg("--umi")
+        .arg(test_files.umi);
+
+    cmd.assert().code(8).stderr(predicate::str::contains(
+        "read2_truncated.fq ended unexpectedly after 2 records",
+    ));
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_truncated_record() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // The third record of read 2 ends after its sequence.
+    let read2 = std::fs::read_to_string(&test_files.read2).unwrap();
+    let truncated = read2.lines().take(10).collect::<Vec<_>>().join("\n") + "\n";
+    temp_dir
+        .child("read2_truncated.fq")
+        .write_str(&truncated)
+        .unwrap();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(temp_dir.path().join("read2_truncated.fq"))
+        .arg("--umi")
+        .arg(test_files.umi);
+
+    cmd.assert().code(8).stderr(predicate::str::contains(
+        "read2_truncated.fq ended unexpectedly after 2 records",
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_umi_input_longer() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // Both read files end after two records, while the UMI file continues.
+    for (read, name) in [
+        (&test_files.read1, "read1_short.fq"),
+        (&test_files.read2, "read2_short.fq"),
+    ] {
+        let records = std::fs::read_to_string(read).unwrap();
+        let short = records.lines().take(8).collect::<Vec<_>>().join("\n") + "\n";
+        temp_dir.child(name).write_str(&short).unwrap();
+    }
+    cmd.arg("external")
+        .arg("--in")
+        .arg(temp_dir.path().join("read1_short.fq"))
+        .arg("--in2")
+        .arg(temp_dir.path().join("read2_short.fq"))
+        .arg("--umi")
+        .arg(test_files.umi);
+
+    cmd.assert().code(8).stderr(
+        predicate::str::contains("read1_short.fq and ")
+            .and(predicate::str::contains(
+                "read2_short.fq ended after 2 records, while the UMI input has more records",
+            ))
+            .and(predicate::str::contains("umi.fq ended").not()),
+    );
+    temp_dir
+        .child("read1_short_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_exit_code_invalid_fastq() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // The third record lacks the leading @.
+    let umi = std::fs::read_to_string(&test_files.umi).unwrap();
+    let mut lines: Vec<&str> = umi.lines().collect();
+    let header = lines[8].trim_start_matches('@').to_string();
+    lines[8] = &header;
+    temp_dir
+        .child("umi_malformed.fq")
+        .write_str(&(lines.join("\n") + "\n"))
+        .unwrap();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(temp_dir.path().join("umi_malformed.fq"));
+
+    cmd.assert()
+        .code(9)
+        .stderr(predicate::str::contains("Record 3 of"))
+        .stderr(predicate::str::contains("is not valid FastQ"))
+        .stderr(predicate::str::contains("expected '@' at record start"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_umi_table() {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+
+    // Tabulate the UMIs of the FastQ file, which must then yield the same outputs.
+    let umis = std::fs::read_to_string(&test_files.umi).unwrap();
+    let lines: Vec<&str> = umis.lines().collect();
+    let table: String = lines
+        .chunks(4)
+        .map(|record| {
+            let id = record[0][1..].split(' ').next().unwrap();
+            format!("{}\t{}\t{}\n", id, record[1], record[3])
+        })
+        .collect();
+    temp_dir.child("umis.tsv").write_str(&table).unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi-table")
+        .arg(temp_dir.path().join("umis.tsv"))
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read2);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    let test_output = test_output.unwrap();
+    let results1 =
+        auxiliary::verify_file_contents(&test_files.new_output_read1, &test_output.header_read1);
+    let results2 =
+        auxiliary::verify_file_contents(&test_files.new_output_read2, &test_output.header_read2);
+    assert!(results1.is_ok());
+    assert!(results2.is_ok());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_umi_table_missing_read() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    temp_dir
+        .child("umis.tsv")
+        .write_str("SCILIFELAB:500:NGISTLM:1:1101:19994:1016\tCCTGAGACC\n")
+        .unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi-table")
+        .arg(temp_dir.path().join("umis.tsv"));
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "No UMI found for read SCILIFELAB:500:NGISTLM:1:1101:25581:1016: the read is not listed in the UMI table.",
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_umi_table_detects_unpaired_reads() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // The records of read 2 are shuffled, so the second read of each pair belongs to another pair.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1 1:N:0:1\nACGT\n+\nFFFF\n@READ2 1:N:0:1\nACGT\n+\nFFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ2 3:N:0:1\nTTGG\n+\nFFFF\n@READ1 3:N:0:1\nTTGG\n+\nFFFF\n")
+        .unwrap();
+    let table = temp_dir.child("umis.tsv");
+    table.write_str("READ1\tGGCC\nREAD2\tAATT\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi-table")
+        .arg(table.path());
+
+    cmd.assert().code(7).stderr(predicate::str::contains(
+        "IDs of UMI and read records mismatch",
+    ));
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_umi_in_read_with_truncated_qualities() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // The quality line of the first read is shorter than the UMI.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1 1:N:0:1\nACGTACGT\n+\nFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1 3:N:0:1\nTTGGTTGG\n+\nFFFFFFFF\n")
+        .unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi-in-read")
+        .arg("r1:4");
+
+    cmd.assert().failure().stderr(
+        predicate::str::contains(
+            "No UMI found for read READ1: the read has fewer quality scores than the UMI length 4.",
+        )
+        .and(predicate::str::contains("panicked").not()),
+    );
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_umi_in_read() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi-in-read")
+        .arg("r1:8")
+        .arg("--out")
+        .arg(&test_files.new_output_read1);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    // The UMI is moved from the start of read 1 to the headers.
+    let output = std::fs::read_to_string(&test_files.new_output_read1).unwrap();
+    let mut lines = output.lines();
+    assert_eq!(
+        lines.next(),
+        Some("@SCILIFELAB:500:NGISTLM:1:1101:19994:1016:AATCGCCG 1:N:0:GCTTCAGGGT+AAGGTAGCGT")
+    );
+    assert!(lines.next().unwrap().starts_with("CCAGTTAAATAGC"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_umi_from_index() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi-from-index")
+        .arg("--out2")
+        .arg(&test_files.new_output_read2);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    let output = std::fs::read_to_string(&test_files.new_output_read2).unwrap();
+    assert!(output.starts_with(
+        "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016:AAGGTAGCGT 3:N:0:GCTTCAGGGT+AAGGTAGCGT\n"
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_several_umi_sources() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--umi-from-index");
+
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_keeps_existing_output_without_terminal() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    temp_dir
+        .child("read1_out.fq")
+        .write_str("existing")
+        .unwrap();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(test_files.new_output_read1);
+
+    // Without a terminal, the overwrite prompt can not be answered and is not shown.
+    cmd.assert()
+        .code(4)
+        .stderr(predicate::str::contains(
+            "exists, but must not be overwritten",
+        ))
+        .stderr(predicate::str::contains("Overwrite?").not());
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::str::diff("existing"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_overwrite_backup_keeps_existing_output() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    temp_dir
+        .child("read1_out.fq")
+        .write_str("existing")
+        .unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(test_files.new_output_read1)
+        .arg("--overwrite")
+        .arg("backup");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("read1_out.fq was moved to"))
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    temp_dir
+        .child("read1_out.fq.bak")
+        .assert(predicate::str::diff("existing"));
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::str::starts_with("@SCILIFELAB"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_overwrite_never_fails_on_existing_output() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    temp_dir
+        .child("read2_out.fq")
+        .write_str("existing")
+        .unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out2")
+        .arg(test_files.new_output_read2)
+        .env("UMI_TRANSFER_OVERWRITE", "never");
+
+    cmd.assert().code(4).stderr(predicate::str::contains(
+        "read2_out.fq exists, but must not be overwritten. Use --overwrite always or --overwrite backup to replace it.",
+    ));
+    temp_dir
+        .child("read2_out.fq")
+        .assert(predicate::str::diff("existing"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_force_and_overwrite() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--force")
+        .arg("--overwrite")
+        .arg("never");
+
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_outdir_and_suffix() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let outdir = temp_dir.child("scratch");
+    outdir.create_dir_all().unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--outdir")
+        .arg(outdir.path())
+        .arg("--suffix")
+        .arg(".umi");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    outdir
+        .child("read1.umi.fq")
+        .assert(predicate::path::is_file());
+    outdir
+        .child("read2.umi.fq")
+        .assert(predicate::path::is_file());
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_with_name_template() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--name-template")
+        .arg("{sample}_{read}.umi.fastq.gz")
+        .arg("--sample")
+        .arg("S1")
+        .arg("--gzip");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    // The extension of the template is kept, because it already matches the compression.
+    temp_dir
+        .child("S1_R1.umi.fastq.gz")
+        .assert(predicate::path::is_file());
+    temp_dir
+        .child("S1_R2.umi.fastq.gz")
+        .assert(predicate::path::is_file());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_invalid_name_template() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--name-template")
+        .arg("{sample}.fastq");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Output name '{sample}.fastq' is invalid: the template must contain {read}.",
+    ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_refuses_to_overwrite_input_without_extension() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // No suffix can be inserted into a name without extension, so the output would be the input.
+    let input = temp_dir.child("reads");
+    input.write_file(&test_files.read1).unwrap();
+    let content = std::fs::read_to_string(input.path()).unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(input.path())
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--force");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "reads is the same file as the input",
+    ));
+    input.assert(predicate::str::diff(content));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_refuses_to_overwrite_input_through_symlink() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let link = temp_dir.child("link.fq");
+    link.symlink_to_file(&test_files.read2).unwrap();
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(&test_files.read1)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out2")
+        .arg(link.path())
+        .arg("--overwrite")
+        .arg("backup");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "link.fq is the same file as the input",
+    ));
+    assert!(test_files.read2.exists());
+    link.assert(predicate::path::is_symlink());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_fails_with_same_file_for_both_outputs() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read1);
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "are the same file. Please choose different names for the outputs.",
+    ));
+    temp_dir
+        .child("read1_out.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_strict_fails_on_malformed_read() {
+    let (mut cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+    // The first record of the test data has one quality score less than bases, which the FastQ
+    // parser accepts.
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--strict");
+
+    cmd.assert().code(9).stderr(predicate::str::contains(
+        "read1.fq is malformed: 102 bases, but 101 quality scores.",
+    ));
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_strict_fails_on_malformed_umi() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1 1:N:0:1\nACGT\n+\nFFFF\n@READ2 1:N:0:1\nACGT\n+\nFFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1 3:N:0:1\nTTGG\n+\nFFFF\n@READ2 3:N:0:1\nTTGG\n+\nFFFF\n")
+        .unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1 2:N:0:1\nGGCC\n+\nFFFF\n@READ2 2:N:0:1\nGG.C\n+\nFFFF\n")
+        .unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .env("UMI_TRANSFER_STRICT", "true");
+
+    cmd.assert()
+        .code(9)
+        .stderr(
+            predicate::str::contains("Record 2 of").and(predicate::str::contains(
+                "umi.fq is malformed: '.' is not a nucleotide code.",
+            )),
+        );
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_converts_phred64_reads_to_phred33() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // Legacy HiSeq reads with Phred+64 qualities and UMIs with Phred+33 qualities.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1 1:N:0:1\nACGT\n+\nhhgB\n@READ2 1:N:0:1\nACGT\n+\nhhhh\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1 3:N:0:1\nTTGG\n+\nhhhh\n@READ2 3:N:0:1\nTTGG\n+\nBhhh\n")
+        .unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1 2:N:0:1\nGGCC\n+\nFF#F\n@READ2 2:N:0:1\nGGCC\n+\nFFFF\n")
+        .unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .arg("--position")
+        .arg("inline")
+        .arg("--convert-quals-to")
+        .arg("phred33");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("read1.fq: phred64"))
+        .stdout(predicate::str::contains("umi.fq: phred33"))
+        .stdout(predicate::str::contains("Processed 2 records"));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1 1:N:0:1\nGGCCACGT\n+\nFF#FIIH#\n@READ2 1:N:0:1\nGGCCACGT\n+\nFFFFIIII\n",
+        ));
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1 3:N:0:1\nGGCCTTGG\n+\nFF#FIIII\n@READ2 3:N:0:1\nGGCCTTGG\n+\nFFFF#III\n",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_detects_quality_encoding_before_touching_outputs() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // The separator line of the first record is missing, so the encoding cannot be detected.
+    let read1 = temp_dir.child("read1.fq");
+    read1.write_str("@READ1 1:N:0:1\nACGT\nFFFF\n").unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2.write_str("@READ1 3:N:0:1\nTTGG\n+\nFFFF\n").unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1 2:N:0:1\nGGCC\n+\nFFFF\n").unwrap();
+    let existing = temp_dir.child("read1_with_UMIs.fq");
+    existing.write_str("existing").unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .arg("--convert-quals-to")
+        .arg("phred33")
+        .arg("--overwrite")
+        .arg("backup")
+        .arg("--checkpoint")
+        .arg("1");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Failed to detect the quality encoding",
+        ))
+        .stdout(predicate::str::contains("was moved to").not());
+
+    // The existing output is neither moved away nor replaced, and no progress is saved.
+    existing.assert(predicate::str::diff("existing"));
+    temp_dir
+        .child("read1_with_UMIs.fq.bak")
+        .assert(predicate::path::missing());
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::path::missing());
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 4);
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_converts_spacer_quality_to_phred64() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let read1 = temp_dir.child("read1.fq");
+    read1.write_str("@READ1 1:N:0:1\nACGT\n+\nhhgB\n").unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2.write_str("@READ1 3:N:0:1\nTTGG\n+\nhhhh\n").unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1 2:N:0:1\nGGCC\n+\nFF#F\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path())
+        .arg("--position")
+        .arg("inline")
+        .arg("--inline-reads")
+        .arg("r1")
+        .arg("--spacer")
+        .arg("TT")
+        .arg("--convert-quals-to")
+        .arg("phred64");
+
+    cmd.assert().success();
+
+    // The default spacer quality 'I' (Q40) becomes 'h' like the UMI qualities.
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1 1:N:0:1\nGGCCTTACGT\n+\neeBehhhhgB\n",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn external_matches_reads_with_legacy_suffixes() {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    // Older Illumina pipelines number the reads of a pair with a suffix instead of a comment.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1/1\nACGT\n+\nFFFF\n@READ2/1\nACGT\n+\nFFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1/3\nTTGG\n+\nFFFF\n@READ2/3\nTTGG\n+\nFFFF\n")
+        .unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1/2\nGGCC\n+\nFFFF\n@READ2/2\nAATT\n+\nFFFF\n")
+        .unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("external")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path());
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Processed 2 records"));
+
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1:GGCC/1\nACGT\n+\nFFFF\n@READ2:AATT/1\nACGT\n+\nFFFF\n",
+        ));
+    temp_dir
+        .child("read2_with_UMIs.fq")
+        .assert(predicate::str::diff(
+            "@READ1:GGCC/3\nTTGG\n+\nFFFF\n@READ2:AATT/3\nTTGG\n+\nFFFF\n",
+        ));
+
     temp_dir.close().unwrap();
 }
@@ -1,3 +1,4 @@
+use assert_cmd::cargo::cargo_bin_cmd;
 use assert_fs::prelude::*;
 use auxiliary::{verify_file_binary, verify_file_contents};
 use predicates::prelude::*;
@@ -502,3 +503,207 @@ fn external_switch_umi_and_read2() -> TestResult {
     temp_dir.close()?;
     Ok(())
 }
+
+#[test]
+fn external_produces_correct_output_multiple_inputs() -> TestResult {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(&test_files.read1)
+        .arg(&test_files.read1_gz)
+        .arg("--in2")
+        .arg(&test_files.read2)
+        .arg(&test_files.read2_gz)
+        .arg("--umi")
+        .arg(&test_files.umi)
+        .arg(&test_files.umi_gz);
+
+    cmd.assert().success();
+
+    // The records of both inputs are written one after another to the same output file.
+    let reference = test_output.unwrap();
+    for (output, reference) in [
+        ("read1_with_UMIs.fq", &reference.header_read1),
+        ("read2_with_UMIs.fq", &reference.header_read2),
+    ] {
+        let records = std::fs::read_to_string(reference)?;
+        let merged_reference = temp_dir.child(format!("merged_{output}"));
+        merged_reference.write_str(&records.repeat(2))?;
+        verify_file_contents(
+            &temp_dir.child(output).to_path_buf(),
+            &merged_reference.to_path_buf(),
+        )?;
+    }
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn external_resumes_from_checkpoint() -> TestResult {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    let test_output = test_output.unwrap();
+
+    // Simulate a run that was interrupted after 4 records: The temporary outputs contain the
+    // first 4 records of the expected output followed by an incompletely written record.
+    let mut lengths = Vec::new();
+    for (output, reference) in [
+        (&test_files.new_output_read1, &test_output.header_read1),
+        (&test_files.new_output_read2, &test_output.header_read2),
+    ] {
+        let content = std::fs::read_to_string(reference)?;
+        let complete: String = content.split_inclusive('\n').take(16).collect();
+        lengths.push(complete.len());
+        std::fs::write(
+            format!("{}.tmp", output.display()),
+            format!("{}@SCILIFELAB:500:NGIS", complete),
+        )?;
+    }
+    temp_dir.child("read1_out.fq.checkpoint").write_str(&format!(
+        "records = 4\n\n[[outputs]]\npath = \"{}\"\nlength = {}\n\n[[outputs]]\npath = \"{}\"\nlength = {}\n",
+        test_files.new_output_read1.display(),
+        lengths[0],
+        test_files.new_output_read2.display(),
+        lengths[1]
+    ))?;
+
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--out")
+        .arg(&test_files.new_output_read1)
+        .arg("--out2")
+        .arg(&test_files.new_output_read2)
+        .arg("--resume");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Resuming after 4 records."))
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    verify_file_contents(&test_files.new_output_read1, &test_output.header_read1)?;
+    verify_file_contents(&test_files.new_output_read2, &test_output.header_read2)?;
+    temp_dir
+        .child("read1_out.fq.checkpoint")
+        .assert(predicate::path::missing());
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn external_resumes_again_after_failed_resume() -> TestResult {
+    let (_cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    let test_output = test_output.unwrap();
+
+    // Simulate a run with a checkpoint every 2 records that was interrupted after 4 records.
+    let mut lengths = Vec::new();
+    for (output, reference) in [
+        (&test_files.new_output_read1, &test_output.header_read1),
+        (&test_files.new_output_read2, &test_output.header_read2),
+    ] {
+        let content = std::fs::read_to_string(reference)?;
+        let complete: String = content.split_inclusive('\n').take(16).collect();
+        lengths.push(complete.len());
+        std::fs::write(format!("{}.tmp", output.display()), complete)?;
+    }
+    temp_dir.child("read1_out.fq.checkpoint").write_str(&format!(
+        "records = 4\nevery = 2\n\n[[outputs]]\npath = \"{}\"\nlength = {}\n\n[[outputs]]\npath = \"{}\"\nlength = {}\n",
+        test_files.new_output_read1.display(),
+        lengths[0],
+        test_files.new_output_read2.display(),
+        lengths[1]
+    ))?;
+
+    // UMIs that match the reads up to record 6 and are shuffled afterwards.
+    let umi = std::fs::read_to_string(&test_files.umi)?;
+    let shuffled = std::fs::read_to_string(&test_files.umi_shuffle)?;
+    let mixed: String = umi
+        .split_inclusive('\n')
+        .take(24)
+        .chain(shuffled.split_inclusive('\n').skip(24))
+        .collect();
+    let umi_mixed = temp_dir.child("umi_mixed.fq");
+    umi_mixed.write_str(&mixed)?;
+
+    let resume = |umi: &std::path::Path| {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.arg("external")
+            .arg("--in")
+            .arg(&test_files.read1)
+            .arg("--in2")
+            .arg(&test_files.read2)
+            .arg("--umi")
+            .arg(umi)
+            .arg("--out")
+            .arg(&test_files.new_output_read1)
+            .arg("--out2")
+            .arg(&test_files.new_output_read2)
+            .arg("--resume");
+        cmd
+    };
+
+    // The failed resume keeps the temporary outputs and saves a checkpoint after 6 records.
+    resume(umi_mixed.path()).assert().code(7);
+    temp_dir
+        .child("read1_out.fq.tmp")
+        .assert(predicate::path::exists());
+    temp_dir
+        .child("read2_out.fq.tmp")
+        .assert(predicate::path::exists());
+    temp_dir
+        .child("read1_out.fq.checkpoint")
+        .assert(predicate::str::starts_with("records = 6\n"));
+
+    resume(&test_files.umi)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Resuming after 6 records."))
+        .stdout(predicate::str::contains("Processed 10 records"));
+
+    verify_file_contents(&test_files.new_output_read1, &test_output.header_read1)?;
+    verify_file_contents(&test_files.new_output_read2, &test_output.header_read2)?;
+
+    temp_dir.close()?;
+    Ok(())
+}
+
+#[test]
+fn external_with_checkpoints_produces_correct_output() -> TestResult {
+    let (mut cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    cmd.arg("external")
+        .arg("--in")
+        .arg(test_files.read1)
+        .arg("--in2")
+        .arg(test_files.read2)
+        .arg("--umi")
+        .arg(test_files.umi)
+        .arg("--gzip")
+        .arg("--checkpoint")
+        .arg("3");
+
+    cmd.assert().success();
+
+    // The output consists of several gzip members, but has the same content.
+    let decompressed = temp_dir.child("read1_decompressed.fq");
+    let mut decoder = flate2::read::MultiGzDecoder::new(std::fs::File::open(
+        temp_dir.child("read1_with_UMIs.fq.gz").path(),
+    )?);
+    let mut content = String::new();
+    std::io::Read::read_to_string(&mut decoder, &mut content)?;
+    decompressed.write_str(&content)?;
+    verify_file_contents(
+        &decompressed.path().to_path_buf(),
+        &test_output.unwrap().header_read1,
+    )?;
+    temp_dir
+        .child("read1_with_UMIs.fq.gz.checkpoint")
+        .assert(predicate::path::missing());
+
+    temp_dir.close()?;
+    Ok(())
+}
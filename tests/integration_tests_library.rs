@@ -0,0 +1,84 @@
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use umi_transfer::{RuntimeErrors, UMIDestination, UmiInput, UmiTransfer};
+
+#[path = "auxiliary.rs"]
+mod auxiliary;
+
+#[test]
+fn library_transfers_umis_to_header() {
+    let (_cmd, temp_dir, test_files, test_output) = auxiliary::setup_integration_test(true);
+    let test_output = test_output.unwrap();
+
+    let summary = UmiTransfer::new(test_files.read1, test_files.read2, test_files.umi)
+        .destination(UMIDestination::Header)
+        .delim(":")
+        .out(&test_files.new_output_read1)
+        .out2(&test_files.new_output_read2)
+        .out_umi(temp_dir.path().join("umis.fq"))
+        .threads(1)
+        .run()
+        .unwrap();
+
+    assert_eq!(summary.records, 10);
+    assert_eq!(summary.output1, test_files.new_output_read1);
+    assert_eq!(summary.output_umi, Some(temp_dir.path().join("umis.fq")));
+
+    auxiliary::verify_file_contents(&summary.output1, &test_output.header_read1).unwrap();
+    auxiliary::verify_file_contents(&summary.output2, &test_output.header_read2).unwrap();
+    temp_dir.child("umis.fq").assert(predicate::path::exists());
+    assert_eq!(
+        summary.output_umi_bytes,
+        Some(
+            std::fs::metadata(temp_dir.path().join("umis.fq"))
+                .unwrap()
+                .len()
+        )
+    );
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn library_returns_typed_errors() {
+    let (_cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let error = UmiTransfer::new(test_files.read1, test_files.read2, test_files.umi_shuffle)
+        .threads(1)
+        .run()
+        .unwrap_err();
+
+    assert!(matches!(
+        error.downcast_ref::<RuntimeErrors>(),
+        Some(RuntimeErrors::ReadIDMismatch)
+    ));
+    temp_dir
+        .child("read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn library_rejects_empty_inputs() {
+    let (_cmd, temp_dir, test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let error = UmiTransfer::with_umis(Vec::new(), Vec::new(), UmiInput::FastQ(Vec::new()))
+        .run()
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<RuntimeErrors>(),
+        Some(RuntimeErrors::NoInputFiles(input)) if input == "the UMIs"
+    ));
+
+    let error = UmiTransfer::with_umis(
+        vec![test_files.read1],
+        Vec::new(),
+        UmiInput::FastQ(vec![test_files.umi]),
+    )
+    .run()
+    .unwrap_err();
+    assert_eq!(error.to_string(), "No input files were given for read 2.");
+
+    temp_dir.close().unwrap();
+}
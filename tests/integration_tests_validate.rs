@@ -0,0 +1,222 @@
+use assert_fs::fixture::{ChildPath, TempDir};
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[path = "auxiliary.rs"]
+mod auxiliary;
+
+// The reference read files have one quality score less than bases per record,
+// which validation rightfully reports. Therefore, write small well-formed inputs.
+fn write_consistent_input(temp_dir: &TempDir) -> (ChildPath, ChildPath, ChildPath) {
+    let ids = [
+        "SCILIFELAB:500:NGISTLM:1:1101:19994:1016",
+        "SCILIFELAB:500:NGISTLM:1:1101:25581:1016",
+    ];
+    let mut reads = [String::new(), String::new(), String::new()];
+    for id in ids {
+        reads[0].push_str(&format!("@{id} 1:N:0:GCTTCAGGGT\nACGTACGT\n+\nFFFFFFFF\n"));
+        reads[1].push_str(&format!("@{id} 3:N:0:GCTTCAGGGT\nTTGCAACG\n+\nFFFF:FFF\n"));
+        reads[2].push_str(&format!(
+            "@{id} 2:N:0:GCTTCAGGGT\nCCTGAGACC\n+\nFFFFFFFFF\n"
+        ));
+    }
+    let read1 = temp_dir.child("consistent_read1.fq");
+    read1.write_str(&reads[0]).unwrap();
+    let read2 = temp_dir.child("consistent_read2.fq");
+    read2.write_str(&reads[1]).unwrap();
+    let umi = temp_dir.child("consistent_umi.fq");
+    umi.write_str(&reads[2]).unwrap();
+    (read1, read2, umi)
+}
+
+#[test]
+fn validate_succeeds_with_consistent_input() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let (read1, read2, umi) = write_consistent_input(&temp_dir);
+
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path());
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Validating input files"))
+        .stdout(predicate::str::contains("Validated 2 records"))
+        .stdout(predicate::str::contains("Input files are consistent"));
+
+    // No output must be written during validation.
+    temp_dir
+        .child("consistent_read1_with_UMIs.fq")
+        .assert(predicate::path::missing());
+    temp_dir
+        .child("consistent_read2_with_UMIs.fq")
+        .assert(predicate::path::missing());
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn validate_fails_on_read_id_mismatch() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let (read1, read2, _umi) = write_consistent_input(&temp_dir);
+
+    // UMI file with the two records in reverse order.
+    let shuffled_umi = temp_dir.child("shuffled_umi.fq");
+    shuffled_umi
+        .write_str(concat!(
+            "@SCILIFELAB:500:NGISTLM:1:1101:25581:1016 2:N:0:GCTTCAGGGT\nCCTGAGACC\n+\nFFFFFFFFF\n",
+            "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016 2:N:0:GCTTCAGGGT\nCCTGAGACC\n+\nFFFFFFFFF\n",
+        ))
+        .unwrap();
+
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(shuffled_umi.path());
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Validated 2 records"))
+        .stderr(predicate::str::contains(
+            "Failed to validate the input files",
+        ))
+        .stderr(predicate::str::contains("but the UMI record has ID"))
+        .stderr(predicate::str::contains("found 4 problem(s)"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn validate_matches_reads_like_the_transfer() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    // Reads with legacy suffixes and a UMI of an earlier run still belong to the UMI records.
+    let read1 = temp_dir.child("read1.fq");
+    read1
+        .write_str("@READ1:CCTGAGACC/1\nACGT\n+\nFFFF\n@READ2:A/1\nACGT\n+\nFFFF\n")
+        .unwrap();
+    let read2 = temp_dir.child("read2.fq");
+    read2
+        .write_str("@READ1:CCTGAGACC/3\nTTGG\n+\nFFFF\n@READ2:A/3\nTTGG\n+\nFFFF\n")
+        .unwrap();
+    let umi = temp_dir.child("umi.fq");
+    umi.write_str("@READ1/2\nCCTGAGACC\n+\nFFFFFFFFF\n@READ2/2\nCCTGAGACC\n+\nFFFFFFFFF\n")
+        .unwrap();
+
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path());
+
+    // A short name field like `:A` is no UMI, so only the second pair is reported.
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Record 1").not())
+        .stderr(predicate::str::contains(
+            "read1.fq has ID READ2:A/1, but the UMI record has ID READ2/2.",
+        ))
+        .stderr(predicate::str::contains("found 2 problem(s)"));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn validate_fails_on_unequal_record_counts() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let (read1, read2, _umi) = write_consistent_input(&temp_dir);
+
+    // UMI file that only contains the first record of the reads.
+    let truncated_umi = temp_dir.child("umi_truncated.fq");
+    truncated_umi
+        .write_str(
+            "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016 2:N:0:GCTTCAGGGT\nCCTGAGACC\n+\nFFFFFFFFF\n",
+        )
+        .unwrap();
+
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(truncated_umi.path());
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Validated 1 records"))
+        .stderr(predicate::str::contains(
+            "umi_truncated.fq ended after 1 records",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn validate_fails_on_unequal_file_counts() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+    let (read1, read2, umi) = write_consistent_input(&temp_dir);
+
+    // The records of both read files are split into two lanes, but a single UMI file is given.
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path());
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Validating input files").not())
+        .stderr(predicate::str::contains(
+            "Received 2 file(s) for read 1, 2 for read 2 and 1 for the UMIs.",
+        ));
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn validate_fails_on_sequence_quality_length_mismatch() {
+    let (mut cmd, temp_dir, _test_files, _test_output) = auxiliary::setup_integration_test(false);
+
+    let header = "@SCILIFELAB:500:NGISTLM:1:1101:19994:1016";
+    let read1 = temp_dir.child("short_read1.fq");
+    read1
+        .write_str(&format!(
+            "{header} 1:N:0:GCTTCAGGGT\nACGTACGT\n+\nFFFFFFFF\n"
+        ))
+        .unwrap();
+    let read2 = temp_dir.child("short_read2.fq");
+    read2
+        .write_str(&format!("{header} 3:N:0:GCTTCAGGGT\nACGTACGT\n+\nFFFFFF\n"))
+        .unwrap();
+    let umi = temp_dir.child("short_umi.fq");
+    umi.write_str(&format!("{header} 2:N:0:GCTTCAGGGT\nCCTGA\n+\nFFFFF\n"))
+        .unwrap();
+
+    cmd.arg("validate")
+        .arg("--in")
+        .arg(read1.path())
+        .arg("--in2")
+        .arg(read2.path())
+        .arg("--umi")
+        .arg(umi.path());
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "short_read2.fq has 8 bases, but 6 quality scores",
+    ));
+
+    temp_dir.close().unwrap();
+}